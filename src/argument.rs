@@ -0,0 +1,201 @@
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The type of an input argument, which decides what values it accepts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ArgType {
+    String,
+    Path,
+    Url,
+    Integer,
+    Float,
+    Bool,
+    /// A type arr doesn't know, as written, whose values are taken as strings
+    Unknown(String),
+}
+
+impl ArgType {
+    /// Check a value the user gave, returning it as the test should see it.
+    pub fn coerce(&self, value: &str) -> Option<String> {
+        match self {
+            ArgType::String | ArgType::Unknown(_) => Some(value.to_string()),
+            ArgType::Path => Some(normalize_path(value)),
+            ArgType::Url => is_url(value.trim()).then(|| value.trim().to_string()),
+            ArgType::Integer => value.trim().parse::<i64>().ok().map(|n| n.to_string()),
+            ArgType::Float => value
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(|_| value.trim().to_string()),
+            ArgType::Bool => match value.trim().to_lowercase().as_str() {
+                "true" | "yes" | "on" | "1" => Some("true".to_string()),
+                "false" | "no" | "off" | "0" => Some("false".to_string()),
+                _ => None,
+            },
+        }
+    }
+
+    /// What a value of the type looks like, for error messages.
+    pub fn expected(&self) -> &'static str {
+        match self {
+            ArgType::String | ArgType::Unknown(_) => "a string",
+            ArgType::Path => "a path",
+            ArgType::Url => "a URL like `https://example.com/file`",
+            ArgType::Integer => "a whole number",
+            ArgType::Float => "a number",
+            ArgType::Bool => "true or false",
+        }
+    }
+}
+
+impl fmt::Display for ArgType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ArgType::String => "string",
+            ArgType::Path => "path",
+            ArgType::Url => "url",
+            ArgType::Integer => "integer",
+            ArgType::Float => "float",
+            ArgType::Bool => "bool",
+            ArgType::Unknown(name) => name,
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ArgType {
+    type Err = String;
+
+    // the atomics aren't consistent about case
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "string" => Ok(ArgType::String),
+            "path" => Ok(ArgType::Path),
+            "url" => Ok(ArgType::Url),
+            "integer" => Ok(ArgType::Integer),
+            "float" => Ok(ArgType::Float),
+            "bool" | "boolean" => Ok(ArgType::Bool),
+            _ => Err(format!(
+                "unknown argument type `{}`, expected string, path, url, integer, float or bool",
+                s
+            )),
+        }
+    }
+}
+
+impl Serialize for ArgType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// one unknown type must not make the whole technique unreadable, lint reports it instead
+impl<'de> Deserialize<'de> for ArgType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        Ok(name.parse().unwrap_or(ArgType::Unknown(name)))
+    }
+}
+
+/// Use this OS's separator and drop repeated ones, keeping a leading `//` or `\\` for UNC paths.
+pub fn normalize_path(path: &str) -> String {
+    let separator = std::path::MAIN_SEPARATOR;
+    let path = match cfg!(windows) {
+        true => path.replace('/', "\\"),
+        false => path.to_string(),
+    };
+
+    let mut normalized = String::with_capacity(path.len());
+    for (i, c) in path.chars().enumerate() {
+        if c == separator && i > 1 && normalized.ends_with(separator) {
+            continue;
+        }
+        normalized.push(c);
+    }
+
+    normalized
+}
+
+fn is_url(value: &str) -> bool {
+    let (scheme, rest) = match value.split_once("://") {
+        Some(parts) => parts,
+        None => return false,
+    };
+
+    let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+
+    scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        // only file URLs may leave out the host
+        && (!host.is_empty() || scheme.eq_ignore_ascii_case("file"))
+        && !rest.is_empty()
+        && !value.chars().any(char::is_whitespace)
+}
+
+/// The candidate closest to a misspelt name, if any is close enough to suggest.
+pub fn closest<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .into_iter()
+        .map(|c| {
+            (
+                strsim::levenshtein(&name.to_lowercase(), &c.to_lowercase()),
+                c,
+            )
+        })
+        .filter(|(distance, _)| *distance <= (name.chars().count() / 3).max(2))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, c)| c)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn coercion() {
+        assert_eq!("Integer".parse(), Ok(ArgType::Integer));
+        assert!("number".parse::<ArgType>().is_err());
+        assert_eq!(
+            serde_yaml::from_str::<ArgType>("number").unwrap(),
+            ArgType::Unknown("number".to_string())
+        );
+        assert_eq!(
+            ArgType::Unknown("number".to_string()).coerce("x y"),
+            Some("x y".to_string())
+        );
+
+        assert_eq!(ArgType::Integer.coerce(" 42 "), Some("42".to_string()));
+        assert_eq!(ArgType::Integer.coerce("+7"), Some("7".to_string()));
+        assert_eq!(ArgType::Integer.coerce("4.2"), None);
+        assert_eq!(ArgType::Float.coerce("4.20"), Some("4.20".to_string()));
+        assert_eq!(ArgType::Float.coerce("NaN"), None);
+        assert_eq!(ArgType::Bool.coerce("Yes"), Some("true".to_string()));
+        assert_eq!(ArgType::Bool.coerce("maybe"), None);
+
+        assert!(ArgType::Url.coerce("https://example.com/a?b#c").is_some());
+        assert!(ArgType::Url.coerce("file:///tmp/payload").is_some());
+        assert!(ArgType::Url.coerce("example.com/payload").is_none());
+        assert!(ArgType::Url.coerce("https:///payload").is_none());
+        assert!(ArgType::Url.coerce("https://exa mple.com").is_none());
+
+        let separator = std::path::MAIN_SEPARATOR;
+        assert_eq!(
+            ArgType::Path.coerce("/tmp//arr/file"),
+            Some(format!("{0}tmp{0}arr{0}file", separator))
+        );
+    }
+
+    #[test]
+    fn closest_name() {
+        let names = ["output_file", "input_file", "remote_url"];
+
+        assert_eq!(closest("output_fil", names), Some("output_file"));
+        assert_eq!(closest("INPUT_FILE", names), Some("input_file"));
+        assert_eq!(closest("something_else", names), None);
+    }
+}
@@ -1,7 +1,11 @@
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::HashMap, env, path::PathBuf, process, time::Duration};
 
-use arr::Arr;
-use clap::{Args, Parser, Subcommand};
+use arr::{
+    Arr, ArrError, InstallReport, Ledger, Lockfile, PrereqStatus, RunOptions, Source, TestFilter,
+    TestOutcome, TestSelector, TestSummary, Timeouts, VarSources,
+};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
 
 use clap_verbosity_flag::Verbosity;
 
@@ -24,6 +28,18 @@ enum Commands {
     Utils(Utils),
     /// Run the clean up for a Test
     Cleanup(Cleanup),
+    /// Run every Test that supports this OS
+    RunAll(RunAll),
+    /// List Techniques and their Tests
+    Ls(Ls),
+    /// Show the details of a Technique or its Tests
+    Info(Info),
+    /// Install the atomics from a local archive or git mirror
+    Get(Get),
+    /// Refresh the installed atomics, or roll back the last update
+    Update(Update),
+    /// Report every problem in the Technique files
+    Lint(Lint),
 }
 
 #[derive(Args)]
@@ -31,39 +47,300 @@ struct Run {
     /// Technique number
     technique: String,
 
-    /// Test number
-    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
-    test_number: u8,
+    /// Test numbers (e.g. `1,3-5`), a test's GUID or a test's name
+    #[arg(default_value = "1", value_name = "TESTS")]
+    tests: TestSelector,
 
-    /// Set a variable
-    #[arg(long = "set-var", short = 's', value_parser = parse_vars, value_name = "VARIABLE=VALUE")]
-    vars: Vec<(String, String)>,
+    #[command(flatten)]
+    vars: VarArgs,
 
-    /// Path to ART yaml files
-    #[arg(short, long, default_value = ".")]
-    path: PathBuf,
+    #[command(flatten)]
+    roots: RootArgs,
 
     /// Cleanup immediately after running the test
     #[arg(short, long, default_value_t = false)]
     cleanup: bool,
+
+    /// Only check whether the test's prerequisites are met
+    #[arg(long, default_value_t = false, conflicts_with_all = ["get_prereqs", "cleanup"])]
+    check_prereqs_only: bool,
+
+    /// Get any missing prerequisites without running the test
+    #[arg(long, default_value_t = false, conflicts_with = "cleanup")]
+    get_prereqs: bool,
+
+    /// Print the fully resolved commands instead of running them
+    #[arg(long, default_value_t = false, conflicts_with_all = ["check_prereqs_only", "get_prereqs"])]
+    dry_run: bool,
+
+    /// Output format for --dry-run, and for the commands that ran and their output
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Run the cleanup if the test fails or is interrupted with Ctrl-C
+    #[arg(long, default_value_t = false)]
+    cleanup_on_failure: bool,
+
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
+
+    #[command(flatten)]
+    ledger: LedgerArgs,
 }
 
 #[derive(Args)]
 struct Cleanup {
+    /// Technique number
+    #[arg(required_unless_present = "pending")]
+    technique: Option<String>,
+
+    /// Test numbers (e.g. `1,3-5`), a test's GUID or a test's name
+    #[arg(default_value = "1", value_name = "TESTS")]
+    tests: TestSelector,
+
+    #[command(flatten)]
+    vars: VarArgs,
+
+    #[command(flatten)]
+    roots: RootArgs,
+
+    /// Clean up every test in the ledger that hasn't been cleaned up yet
+    #[arg(long, default_value_t = false, conflicts_with_all = ["technique", "vars", "vars_file", "profile"])]
+    pending: bool,
+
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
+
+    #[command(flatten)]
+    ledger: LedgerArgs,
+}
+
+#[derive(Args)]
+struct RunAll {
+    #[command(flatten)]
+    roots: RootArgs,
+
+    /// Output format for the results, with the commands that ran and their output
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    #[command(flatten)]
+    vars: VarArgs,
+
+    /// Cleanup immediately after each successful test
+    #[arg(short, long, default_value_t = false)]
+    cleanup: bool,
+
+    /// Number of tests to run at the same time
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    /// Run a test's cleanup if it fails or is interrupted with Ctrl-C
+    #[arg(long, default_value_t = false)]
+    cleanup_on_failure: bool,
+
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
+
+    #[command(flatten)]
+    ledger: LedgerArgs,
+}
+
+#[derive(Args)]
+struct Ls {
+    /// Only list tests whose names or descriptions contain every word
+    search: Option<String>,
+
+    #[command(flatten)]
+    roots: RootArgs,
+
+    /// Only list tests that support this platform
+    #[arg(long)]
+    platform: Option<String>,
+
+    /// Only list tests that use this executor
+    #[arg(long)]
+    executor: Option<String>,
+
+    /// Only list tests that require elevation
+    #[arg(long, default_value_t = false, conflicts_with = "unelevated")]
+    elevated: bool,
+
+    /// Only list tests that do not require elevation
+    #[arg(long, default_value_t = false)]
+    unelevated: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct Info {
     /// Technique number
     technique: String,
 
-    /// Test number
-    #[arg(default_value_t = 1, value_parser = clap::value_parser!(u8).range(1..))]
-    test_number: u8,
+    /// Test numbers (e.g. `1,3-5`), a test's GUID or a test's name, every test if not set
+    #[arg(value_name = "TESTS")]
+    tests: Option<TestSelector>,
 
+    #[command(flatten)]
+    vars: VarArgs,
+
+    #[command(flatten)]
+    roots: RootArgs,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Args)]
+struct Get {
+    /// A .tar, .tar.gz or .zip of the atomics, or a git mirror of them
+    source: PathBuf,
+
+    /// Branch, tag or commit to check out of a git mirror
+    #[arg(long = "ref", value_name = "REF")]
+    reference: Option<String>,
+
+    /// Where to install the atomics
+    #[arg(short, long, default_value = "atomic-red-team-master")]
+    dest: PathBuf,
+}
+
+#[derive(Args)]
+struct Update {
+    /// A .tar, .tar.gz or .zip of the atomics, or a git mirror of them, defaults to the locked source
+    source: Option<PathBuf>,
+
+    /// Branch, tag or commit to check out of a git mirror, including the locked one
+    #[arg(long = "ref", value_name = "REF")]
+    reference: Option<String>,
+
+    /// Where the atomics are installed
+    #[arg(short, long, default_value = "atomic-red-team-master")]
+    dest: PathBuf,
+
+    /// Put back the copy the last update replaced
+    #[arg(long, default_value_t = false, conflicts_with_all = ["source", "reference"])]
+    rollback: bool,
+}
+
+#[derive(Args)]
+struct Lint {
+    #[command(flatten)]
+    roots: RootArgs,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+}
+
+#[derive(Args)]
+struct TimeoutArgs {
+    /// Seconds any phase may run before it is killed
+    #[arg(long, value_name = "SECONDS")]
+    timeout: Option<u64>,
+
+    /// Seconds the prerequisite checks may run, overrides --timeout
+    #[arg(long, value_name = "SECONDS")]
+    check_timeout: Option<u64>,
+
+    /// Seconds the prerequisite downloads may run, overrides --timeout
+    #[arg(long, value_name = "SECONDS")]
+    dependency_timeout: Option<u64>,
+
+    /// Seconds the attack may run, overrides --timeout
+    #[arg(long, value_name = "SECONDS")]
+    attack_timeout: Option<u64>,
+
+    /// Seconds the cleanup may run, overrides --timeout
+    #[arg(long, value_name = "SECONDS")]
+    cleanup_timeout: Option<u64>,
+}
+
+impl TimeoutArgs {
+    fn timeouts(&self) -> Timeouts {
+        let phase = |t: Option<u64>| t.or(self.timeout).map(Duration::from_secs);
+
+        Timeouts {
+            check: phase(self.check_timeout),
+            dependency: phase(self.dependency_timeout),
+            attack: phase(self.attack_timeout),
+            cleanup: phase(self.cleanup_timeout),
+        }
+    }
+}
+
+#[derive(Args)]
+struct VarArgs {
     /// Set a variable
     #[arg(long = "set-var", short = 's', value_parser = parse_vars, value_name = "VARIABLE=VALUE")]
     vars: Vec<(String, String)>,
 
-    /// Path to ART yaml files
-    #[arg(short, long, default_value = ".")]
-    path: PathBuf,
+    /// Read variables from a YAML, TOML or JSON file. ARR_VAR_<VARIABLE> environment variables
+    /// take precedence over it, and --set-var over both
+    #[arg(long, value_name = "FILE")]
+    vars_file: Option<PathBuf>,
+
+    /// Use a profile from the vars file, which takes precedence over the rest of the file
+    #[arg(long, value_name = "NAME", requires = "vars_file")]
+    profile: Option<String>,
+
+    /// Substitute variables as given, instead of quoting them for the executor's shell
+    #[arg(long, default_value_t = false)]
+    raw_vars: bool,
+}
+
+impl VarArgs {
+    fn vars(&self) -> HashMap<String, String> {
+        self.vars.clone().into_iter().collect()
+    }
+
+    fn sources(&self) -> Result<VarSources, ArrError> {
+        VarSources::load(self.vars_file.as_deref(), self.profile.as_deref())
+    }
+}
+
+#[derive(Args)]
+struct RootArgs {
+    /// Path to ART yaml files, repeat to layer several with the earliest taking priority
+    /// [default: $ARR_PATH, or .]
+    #[arg(short, long = "path", value_name = "PATH")]
+    paths: Vec<PathBuf>,
+}
+
+impl RootArgs {
+    fn roots(&self) -> Vec<PathBuf> {
+        if !self.paths.is_empty() {
+            return self.paths.clone();
+        }
+
+        match env::var_os("ARR_PATH").filter(|p| !p.is_empty()) {
+            Some(paths) => env::split_paths(&paths).collect(),
+            None => vec![PathBuf::from(".")],
+        }
+    }
+}
+
+#[derive(Args)]
+struct LedgerArgs {
+    /// File that records the tests still waiting to be cleaned up
+    #[arg(long, value_name = "PATH")]
+    ledger: Option<PathBuf>,
+}
+
+impl LedgerArgs {
+    fn ledger(&self) -> Ledger {
+        Ledger::new(self.ledger.clone().unwrap_or_else(Ledger::default_path))
+    }
 }
 
 #[derive(Subcommand)]
@@ -72,8 +349,6 @@ enum Utils {
     ParseAll(TestPath),
     /// List the number of times each executor is used
     ListExecutors(TestPath),
-    // /// Run all tests for this OS
-    // RunAll(TestPath),
 }
 
 #[derive(Args)]
@@ -83,16 +358,137 @@ struct TestPath {
     path: PathBuf,
 }
 
+/// Split at the first `=`, the value may have more.
 fn parse_vars(s: &str) -> Result<(String, String), String> {
-    if !s.contains('=') {
-        return Err(
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(
             "Please specify variables using the VARIABLE=VALUE format (no spaces around '=')"
                 .to_string(),
-        );
+        ),
+    }
+}
+
+fn print_prereqs(statuses: &[PrereqStatus]) {
+    if statuses.is_empty() {
+        println!("This test has no prerequisites");
+    }
+
+    for status in statuses {
+        let mark = if status.met { "met" } else { "not met" };
+        println!("[{}] {}", mark, status.description);
+    }
+}
+
+fn print_error(e: &ArrError) {
+    match e {
+        ArrError::CannotReadFile(e)
+        | ArrError::CannotParseYaml(e)
+        | ArrError::CannotParseVarsFile(e) => {
+            eprintln!("{}", e.diagnostic())
+        }
+        e => eprintln!("{}", e),
+    }
+
+    if matches!(e, ArrError::TestNotFound { available, .. } if !available.is_empty()) {
+        eprintln!("Select tests by number (e.g. `1,3-5`), GUID or name");
+    }
+}
+
+fn print_table(tests: &[TestSummary]) {
+    let header = [
+        "TECHNIQUE",
+        "DISPLAY NAME",
+        "TEST",
+        "NAME",
+        "PLATFORMS",
+        "EXECUTOR",
+        "ELEVATION",
+    ]
+    .map(String::from);
+
+    let rows: Vec<[String; 7]> = tests
+        .iter()
+        .map(|t| {
+            [
+                t.technique.clone(),
+                t.display_name.clone(),
+                t.test_number.to_string(),
+                t.test_name.clone(),
+                t.platforms.join(","),
+                t.executor.clone(),
+                match t.elevation_required {
+                    true => "required".to_string(),
+                    false => "-".to_string(),
+                },
+            ]
+        })
+        .collect();
+
+    let mut widths = header.clone().map(|h| h.chars().count());
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(&rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", cells.join("  ").trim_end());
+    }
+}
+
+fn print_install(report: &InstallReport) {
+    match &report.previous_version {
+        Some(previous) if previous != &report.version => {
+            println!("Updated from {} to {}", previous, report.version)
+        }
+        Some(_) => println!("Already at {}", report.version),
+        None => println!("Installed {}", report.version),
+    }
+
+    for (label, techniques) in [
+        ("Added", &report.added),
+        ("Changed", &report.changed),
+        ("Removed", &report.removed),
+    ] {
+        if !techniques.is_empty() {
+            println!(
+                "{} ({}): {}",
+                label,
+                techniques.len(),
+                techniques.join(", ")
+            );
+        }
+    }
+
+    if let Some(rollback) = &report.rollback {
+        println!("The previous copy is kept at {}", rollback.display());
+    }
+}
+
+/// A single test prints a single object, several print a list.
+fn print_json_list<T: Serialize>(items: &[T]) {
+    let json = match items.len() {
+        1 => serde_json::to_string_pretty(&items[0]),
+        _ => serde_json::to_string_pretty(items),
+    };
+    match json {
+        Ok(json) => println!("{}", json),
+        Err(e) => eprintln!("{}", e),
     }
-    let split: Vec<&str> = s.split('=').collect();
+}
 
-    Ok((split[0].to_string(), split[1].to_string()))
+fn watch_interrupts(cleanup_on_failure: bool) {
+    if cleanup_on_failure {
+        if let Err(e) = arr::watch_interrupts() {
+            print_error(&e);
+        }
+    }
 }
 
 fn main() {
@@ -106,37 +502,410 @@ fn main() {
 
     match &cli.command {
         Commands::Run(args) => {
-            let vars: HashMap<String, String> = args.vars.clone().into_iter().collect();
-            let test_number = (args.test_number - 1) as usize;
+            let var_sources = match args.vars.sources() {
+                Ok(var_sources) => var_sources,
+                Err(e) => {
+                    print_error(&e);
+                    process::exit(1);
+                }
+            };
+
+            let tests = match Arr::select(
+                &args.technique,
+                &args.tests,
+                args.vars.vars(),
+                args.roots.roots(),
+                Some(arr::index_dir()),
+            ) {
+                Ok(tests) => tests,
+                Err(e) => {
+                    print_error(&e);
+                    process::exit(1);
+                }
+            };
+
+            let tests: Vec<Arr> = tests
+                .into_iter()
+                .map(|arr| {
+                    arr.with_timeouts(args.timeouts.timeouts())
+                        .with_ledger(args.ledger.ledger())
+                        .with_raw_vars(args.vars.raw_vars)
+                        .with_var_sources(var_sources.clone())
+                        .with_cleanup_on_failure(args.cleanup_on_failure)
+                })
+                .collect();
 
-            let arr = Arr::new(args.technique.clone(), vars, test_number, args.path.clone());
+            if args.dry_run {
+                let plans = match tests.iter().map(Arr::plan).collect::<Result<Vec<_>, _>>() {
+                    Ok(plans) => plans,
+                    Err(e) => {
+                        print_error(&e);
+                        process::exit(1);
+                    }
+                };
 
-            if arr.run().is_ok() {
-                println!("Test ran successfully!")
+                match args.format {
+                    Format::Text => {
+                        let plans: Vec<String> = plans.iter().map(|p| p.to_string()).collect();
+                        print!("{}", plans.join("\n"));
+                    }
+                    Format::Json => print_json_list(&plans),
+                }
+                return;
             }
 
-            if args.cleanup {
-                if arr.cleanup().is_ok() {
-                    println!("Cleanup successeful!")
+            watch_interrupts(args.cleanup_on_failure);
+
+            let json = matches!(args.format, Format::Json);
+            let mut reports = Vec::new();
+            let mut failed = false;
+            for arr in &tests {
+                if tests.len() > 1 && !json {
+                    println!("{} Test {}:", arr.technique(), arr.test_num() + 1);
+                }
+
+                if args.check_prereqs_only || args.get_prereqs {
+                    let res = match args.get_prereqs {
+                        true => arr.get_prereqs(),
+                        false => arr.check_prereqs(),
+                    };
+
+                    match res {
+                        Ok(statuses) => print_prereqs(&statuses),
+                        Err(e) => {
+                            print_error(&e);
+                            failed = true;
+                        }
+                    }
+                    continue;
                 }
+
+                let mut report = match arr.run() {
+                    Ok(report) => {
+                        if !json {
+                            println!("Test ran successfully!");
+                        }
+                        report
+                    }
+                    Err(failure) => {
+                        print_error(&failure.source);
+                        failed = true;
+                        *failure.report
+                    }
+                };
+
+                if args.cleanup {
+                    let cleanup = match arr.cleanup() {
+                        Ok(cleanup) => {
+                            if !json {
+                                println!("Cleanup successeful!");
+                            }
+                            cleanup
+                        }
+                        Err(failure) => {
+                            print_error(&failure.source);
+                            failed = true;
+                            *failure.report
+                        }
+                    };
+                    report.phases.extend(cleanup.phases);
+                }
+                reports.push(report);
+
+                if arr::interrupted() {
+                    break;
+                }
+            }
+
+            if json && !reports.is_empty() {
+                print_json_list(&reports);
+            }
+            if failed {
+                process::exit(1);
             }
         }
         Commands::Utils(utils) => match utils {
             Utils::ParseAll(p) => arr::parse_all(&p.path),
-            Utils::ListExecutors(p) => match arr::get_all_executors(&p.path) {
-                Ok(_) => (),
-                Err(e) => eprintln!("{}", e),
-            },
-            // Utils::RunAll(p) => arr::run_all(&p.path),
+            Utils::ListExecutors(p) => {
+                match arr::get_all_executors(&p.path, Some(&arr::index_dir())) {
+                    Ok(_) => (),
+                    Err(e) => print_error(&e),
+                }
+            }
         },
+        Commands::Cleanup(args) if args.pending => {
+            let ledger = args.ledger.ledger();
+
+            match arr::cleanup_pending(
+                &ledger,
+                &args.roots.roots(),
+                Some(&arr::index_dir()),
+                args.timeouts.timeouts(),
+            ) {
+                Ok(results) if results.is_empty() => println!("Nothing to clean up"),
+                Ok(results) => {
+                    let failed = results.iter().any(|(_, res)| res.is_err());
+                    for (entry, res) in results {
+                        let status = match res {
+                            Ok(_) => "Success!".to_string(),
+                            Err(e) => format!("Failure: {}", e),
+                        };
+                        println!(
+                            "Cleaning up: {}, Test: {}...\t{}",
+                            entry.technique, entry.test_number, status
+                        );
+                    }
+                    if failed {
+                        process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    print_error(&e);
+                    process::exit(1);
+                }
+            }
+        }
         Commands::Cleanup(args) => {
-            let vars: HashMap<String, String> = args.vars.clone().into_iter().collect();
-            let test_number = (args.test_number - 1) as usize;
+            let var_sources = match args.vars.sources() {
+                Ok(var_sources) => var_sources,
+                Err(e) => {
+                    print_error(&e);
+                    process::exit(1);
+                }
+            };
+            let technique = args.technique.clone().unwrap_or_default();
+
+            let tests = match Arr::select(
+                &technique,
+                &args.tests,
+                args.vars.vars(),
+                args.roots.roots(),
+                Some(arr::index_dir()),
+            ) {
+                Ok(tests) => tests,
+                Err(e) => {
+                    print_error(&e);
+                    process::exit(1);
+                }
+            };
+
+            let mut failed = false;
+            for arr in tests {
+                let arr = arr
+                    .with_timeouts(args.timeouts.timeouts())
+                    .with_ledger(args.ledger.ledger())
+                    .with_raw_vars(args.vars.raw_vars)
+                    .with_var_sources(var_sources.clone());
+
+                match arr.cleanup() {
+                    Ok(_) => println!("Success!"),
+                    Err(failure) => {
+                        print_error(&failure.source);
+                        failed = true;
+                    }
+                }
+            }
+
+            if failed {
+                process::exit(1);
+            }
+        }
+        Commands::Ls(args) => {
+            let filter = TestFilter {
+                platform: args.platform.clone(),
+                executor: args.executor.clone(),
+                elevation_required: match (args.elevated, args.unelevated) {
+                    (true, _) => Some(true),
+                    (_, true) => Some(false),
+                    _ => None,
+                },
+                search: args.search.clone(),
+            };
+            let tests = match arr::list_tests(&args.roots.roots(), Some(&arr::index_dir()), &filter)
+            {
+                Ok(tests) => tests,
+                Err(e) => {
+                    print_error(&e);
+                    return;
+                }
+            };
+
+            match args.format {
+                Format::Text if tests.is_empty() => println!("No tests match"),
+                Format::Text => print_table(&tests),
+                Format::Json => match serde_json::to_string_pretty(&tests) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => eprintln!("{}", e),
+                },
+            }
+        }
+        Commands::Info(args) => {
+            let var_sources = match args.vars.sources() {
+                Ok(var_sources) => var_sources,
+                Err(e) => {
+                    print_error(&e);
+                    return;
+                }
+            };
+
+            let info = match arr::technique_info(
+                &args.technique,
+                args.tests.as_ref(),
+                args.vars.vars(),
+                &var_sources,
+                args.vars.raw_vars,
+                &args.roots.roots(),
+                Some(&arr::index_dir()),
+            ) {
+                Ok(info) => info,
+                Err(e) => {
+                    print_error(&e);
+                    return;
+                }
+            };
+
+            match args.format {
+                Format::Text => print!("{}", info),
+                Format::Json => match serde_json::to_string_pretty(&info) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => eprintln!("{}", e),
+                },
+            }
+        }
+        Commands::Get(args) => {
+            let res = Source::detect(&args.source, args.reference.clone())
+                .and_then(|source| arr::get(&source, &args.dest));
 
-            let arr = Arr::new(args.technique.clone(), vars, test_number, args.path.clone());
+            match res {
+                Ok(report) => print_install(&report),
+                Err(e) => print_error(&e),
+            }
+        }
+        Commands::Update(args) if args.rollback => match arr::rollback(&args.dest) {
+            Ok(report) => print_install(&report),
+            Err(e) => print_error(&e),
+        },
+        Commands::Update(args) => {
+            let source = match &args.source {
+                Some(path) => Source::detect(path, args.reference.clone()),
+                None => match Lockfile::read(&args.dest) {
+                    Ok(Some(lockfile)) => lockfile.source.with_reference(args.reference.clone()),
+                    Ok(None) => Err(ArrError::InstallFailure(format!(
+                        "{} has no lockfile, please give the source to update from",
+                        args.dest.display()
+                    ))),
+                    Err(e) => Err(e),
+                },
+            };
+
+            match source.and_then(|source| arr::update(&source, &args.dest)) {
+                Ok(report) => print_install(&report),
+                Err(e) => print_error(&e),
+            }
+        }
+        Commands::Lint(args) => {
+            let mut issues = Vec::new();
+
+            for art_path in args.roots.roots() {
+                match arr::lint(&art_path) {
+                    Ok(found) => issues.extend(found),
+                    Err(e) => {
+                        eprintln!("{}: {}", art_path.display(), e);
+                        process::exit(2);
+                    }
+                }
+            }
+
+            match args.format {
+                Format::Text => {
+                    for issue in &issues {
+                        println!("{}", issue);
+                    }
+                    match issues.len() {
+                        0 => println!("No problems found"),
+                        1 => println!("\n1 problem"),
+                        n => println!("\n{} problems", n),
+                    }
+                }
+                Format::Json => match serde_json::to_string_pretty(&issues) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => eprintln!("{}", e),
+                },
+            }
+
+            if !issues.is_empty() {
+                process::exit(1);
+            }
+        }
+        Commands::RunAll(args) => {
+            let var_sources = match args.vars.sources() {
+                Ok(var_sources) => var_sources,
+                Err(e) => {
+                    print_error(&e);
+                    return;
+                }
+            };
+
+            watch_interrupts(args.cleanup_on_failure);
+
+            let options = RunOptions {
+                cleanup: args.cleanup,
+                cleanup_on_failure: args.cleanup_on_failure,
+                max_jobs: args.jobs as usize,
+                timeouts: args.timeouts.timeouts(),
+                ledger: Some(args.ledger.ledger()),
+                index_dir: Some(arr::index_dir()),
+                vars: args.vars.vars(),
+                var_sources,
+                raw_vars: args.vars.raw_vars,
+                // progress lines would break the JSON
+                progress: matches!(args.format, Format::Text),
+            };
+            let results = match arr::run_all(&args.roots.roots(), &options) {
+                Ok(results) => results,
+                Err(e) => {
+                    print_error(&e);
+                    process::exit(1);
+                }
+            };
+
+            let mut passed = 0;
+            let mut failed = 0;
+            let mut skipped = 0;
+
+            for result in &results {
+                match result.outcome {
+                    TestOutcome::Passed => passed += 1,
+                    TestOutcome::Failed(_) => failed += 1,
+                    TestOutcome::Skipped(_) => skipped += 1,
+                }
+            }
+
+            match args.format {
+                Format::Text => {
+                    println!(
+                        "\n{} passed, {} failed, {} skipped",
+                        passed, failed, skipped
+                    );
+
+                    for result in results
+                        .iter()
+                        .filter(|r| matches!(r.outcome, TestOutcome::Failed(_)))
+                    {
+                        println!(
+                            "FAILED: {} Test {}: {}",
+                            result.technique, result.test_number, result.test_name
+                        );
+                    }
+                }
+                Format::Json => match serde_json::to_string_pretty(&results) {
+                    Ok(json) => println!("{}", json),
+                    Err(e) => eprintln!("{}", e),
+                },
+            }
 
-            if arr.cleanup().is_ok() {
-                println!("Success!")
+            if failed > 0 {
+                process::exit(1);
             }
         }
     }
@@ -0,0 +1,146 @@
+use std::path::Path;
+
+use crate::{argument::normalize_path, parse_yaml::AtomicTest};
+
+/// The variables every test has, named as Invoke-AtomicRedTeam names them.
+pub const BUILTINS: &[&str] = &[
+    "PathToAtomicsFolder",
+    "PathToPayloads",
+    "PathToTechniqueFolder",
+];
+
+/// The values of the built-in variables for one test.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Builtins(Vec<(&'static str, String)>);
+
+impl Builtins {
+    pub fn new(atomics_dir: &Path, technique_dir: &Path) -> Self {
+        let atomics = normalize_path(&atomics_dir.to_string_lossy());
+        let technique = normalize_path(&technique_dir.to_string_lossy());
+
+        // like Invoke-AtomicRedTeam, payloads are found with the atomics
+        Self(vec![
+            (BUILTINS[0], atomics.clone()),
+            (BUILTINS[1], atomics),
+            (BUILTINS[2], technique),
+        ])
+    }
+
+    pub fn for_test(test: &AtomicTest) -> Self {
+        Self::new(&test.atomics_dir, &test.technique_dir)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(name, value)| (*name, value.as_str()))
+    }
+
+    /// Replace each `PathToAtomicsFolder` or `$PathToAtomicsFolder`, and the other built-ins, with its path.
+    ///
+    /// Only whole words are replaced, so environment variables like `$env:PathToAtomicsFolder`
+    /// or `%PathToAtomicsFolder%` and `#{}` placeholders are left alone. The rest of the path
+    /// after a built-in is given this platform's separators.
+    pub fn substitute(&self, text: &str) -> String {
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+        let mut substituted = String::with_capacity(text.len());
+        // how much of the text is in `substituted`, and how much has been searched
+        let mut copied = 0;
+        let mut searched = 0;
+
+        while let Some(found) = text[searched..].find("PathTo") {
+            let start = searched + found;
+            let (name, value) = match self
+                .iter()
+                .find(|(name, _)| text[start..].starts_with(name))
+            {
+                Some(builtin) => builtin,
+                None => {
+                    searched = start + 1;
+                    continue;
+                }
+            };
+            let end = start + name.len();
+            searched = end;
+
+            let before = &text[..start];
+            if before.ends_with(is_word)
+                || text[end..].starts_with(is_word)
+                || before.ends_with('%')
+                || before.ends_with("#{")
+                || before.to_lowercase().ends_with("$env:")
+            {
+                continue;
+            }
+
+            let rest = &text[end..];
+            let path_end = rest.find(ends_path).unwrap_or(rest.len());
+
+            // `$PathToAtomicsFolder` is the same variable
+            let start = match before.ends_with('$') {
+                true => start - 1,
+                false => start,
+            };
+            substituted.push_str(&text[copied..start]);
+            substituted.push_str(&normalize_path(&format!(
+                "{}{}",
+                value,
+                separators(&rest[..path_end])
+            )));
+
+            copied = end + path_end;
+            searched = copied;
+        }
+
+        substituted.push_str(&text[copied..]);
+        substituted
+    }
+}
+
+/// Whether a character ends the path written after a built-in.
+fn ends_path(c: char) -> bool {
+    c.is_whitespace() || "'\"`;,|&<>()".contains(c)
+}
+
+/// Give a path this platform's separators.
+fn separators(path: &str) -> String {
+    if cfg!(windows) {
+        return path.to_string();
+    }
+
+    // a backslash at the end escapes whatever ends the path
+    let (path, escape) = match path.strip_suffix('\\') {
+        Some(path) => (path, "\\"),
+        None => (path, ""),
+    };
+
+    format!("{}{}", path.replace('\\', "/"), escape)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn builtins_are_substituted() {
+        let builtins = Builtins::new(Path::new("/art//atomics"), Path::new("/art/atomics/T1"));
+        let sep = std::path::MAIN_SEPARATOR;
+        let atomics = format!("{0}art{0}atomics", sep);
+
+        assert_eq!(
+            builtins.substitute("cat PathToAtomicsFolder/T1/src/a.sh; ls $PathToPayloads"),
+            format!("cat {0}{1}T1{1}src{1}a.sh; ls {0}", atomics, sep)
+        );
+        assert_eq!(
+            builtins.substitute("\"PathToAtomicsFolder\\T1\\bin\\x.exe\" PathToTechniqueFolder"),
+            format!("\"{0}{1}T1{1}bin{1}x.exe\" {0}{1}T1", atomics, sep)
+        );
+        for untouched in [
+            "echo $env:PathToAtomicsFolder",
+            "echo %PathToAtomicsFolder%",
+            "echo #{PathToAtomicsFolder}",
+            "echo MyPathToAtomicsFolder PathToAtomicsFolders PathToNowhere",
+        ] {
+            assert_eq!(builtins.substitute(untouched), untouched);
+        }
+    }
+}
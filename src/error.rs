@@ -1,16 +1,53 @@
-use std::{error::Error, fmt};
+use std::{error::Error, fmt, io, path::PathBuf, time::Duration};
+
+use crate::{argument::ArgType, report::Phase};
 
 #[derive(Debug, PartialEq)]
 pub enum ArrError {
     ArgValueNotFound(String),
     OtherNomError(String),
     OsNotSupported,
-    FileNotFound(String),
-    CannotParseYaml(String),
+    CannotReadFile(SourceError),
+    CannotParseYaml(SourceError),
+    CannotParseVarsFile(SourceError),
     CannotLocateYamlFile,
+    TestNotFound {
+        technique: String,
+        requested: String,
+        /// Each of the technique's tests as `number: name`
+        available: Vec<String>,
+    },
+    AmbiguousTestName(String, Vec<String>),
+    UnknownArgument {
+        name: String,
+        /// The real argument it is most likely a misspelling of
+        closest: Option<String>,
+        available: Vec<String>,
+    },
+    InvalidArgValue {
+        name: String,
+        value: String,
+        expected: ArgType,
+    },
+    CannotQuote(String),
+    /// The arguments whose defaults refer to each other, starting and ending with the same one
+    ArgumentCycle(Vec<String>),
+    VarsFile(String),
+    UnresolvedVariables {
+        unresolved: Vec<Unresolved>,
+        /// The test's input arguments
+        available: Vec<String>,
+    },
     FilePathNotSet(String),
     CommandIoFailure(String),
+    UnknownExecutor(String),
     CommandExecutionFailed,
+    Timeout(Duration),
+    Interrupted,
+    LedgerFailure(String),
+    InstallFailure(String),
+    PrerequisiteNotMet(String),
+    ManualStepsSkipped,
     RootRequired,
     Other(String),
 }
@@ -23,16 +60,269 @@ impl fmt::Display for ArrError {
             ArrError::ArgValueNotFound(s) => write!(f, "{}", s),
             ArrError::OtherNomError(s) => write!(f, "{}", s),
             ArrError::OsNotSupported => write!(f, "The test does not support this OS"),
-            ArrError::FileNotFound(s) => write!(f, "{}", s),
-            ArrError::CannotParseYaml(s) => write!(f, "{}", s),
+            ArrError::CannotReadFile(e) => write!(f, "{}", e),
+            ArrError::CannotParseYaml(e) => write!(f, "{}", e),
+            ArrError::CannotParseVarsFile(e) => write!(f, "{}", e),
             ArrError::CannotLocateYamlFile => write!(f, "Cannot Locate YAML file"),
+            ArrError::TestNotFound {
+                technique,
+                requested,
+                available,
+            } => match available.is_empty() {
+                true => write!(f, "{} has no tests", technique),
+                false => write!(
+                    f,
+                    "{} has no test matching `{}`, its tests are:\n    {}",
+                    technique,
+                    requested,
+                    available.join("\n    ")
+                ),
+            },
+            ArrError::AmbiguousTestName(s, candidates) => write!(
+                f,
+                "`{}` matches more than one test:\n    {}",
+                s,
+                candidates.join("\n    ")
+            ),
+            ArrError::UnknownArgument {
+                name,
+                closest,
+                available,
+            } => match (closest, available.is_empty()) {
+                (Some(closest), _) => write!(
+                    f,
+                    "`{}` is not an input argument, did you mean `{}`?",
+                    name, closest
+                ),
+                (None, true) => write!(f, "`{}` is not an input argument, there are none", name),
+                (None, false) => write!(
+                    f,
+                    "`{}` is not an input argument, they are: {}",
+                    name,
+                    available.join(", ")
+                ),
+            },
+            ArrError::InvalidArgValue {
+                name,
+                value,
+                expected,
+            } => write!(
+                f,
+                "`{}` is not a valid {} for `{}`, expected {}",
+                value,
+                expected,
+                name,
+                expected.expected()
+            ),
+            ArrError::CannotQuote(s) => write!(
+                f,
+                "The value of `{}` cannot be quoted safely for this executor, \
+                 use --raw-vars to substitute it as written",
+                s
+            ),
+            ArrError::ArgumentCycle(names) => write!(
+                f,
+                "The defaults of {} refer to each other",
+                names
+                    .iter()
+                    .map(|n| format!("`{}`", n))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            ArrError::VarsFile(s) => write!(f, "Vars file: {}", s),
+            ArrError::UnresolvedVariables {
+                unresolved,
+                available,
+            } => {
+                match unresolved.len() {
+                    1 => write!(f, "1 variable has no value:")?,
+                    n => write!(f, "{} variables have no value:", n)?,
+                }
+                for u in unresolved {
+                    write!(f, "\n    {}", u)?;
+                }
+                match available.is_empty() {
+                    true => write!(f, "\nThe test has no input arguments"),
+                    false => write!(
+                        f,
+                        "\nThe test's input arguments are: {}",
+                        available.join(", ")
+                    ),
+                }
+            }
             ArrError::FilePathNotSet(s) => write!(f, "{}", s),
             ArrError::Other(s) => write!(f, "{}", s),
             ArrError::RootRequired => write!(f, "Root required"),
             ArrError::CommandIoFailure(s) => write!(f, "{}", s),
+            ArrError::UnknownExecutor(s) => write!(f, "Unknown executor: {}", s),
             ArrError::CommandExecutionFailed => {
                 write!(f, "Command ran, but returned an unsuccess return code")
             }
+            ArrError::Timeout(d) => write!(f, "Command timed out after {:?}", d),
+            ArrError::Interrupted => write!(f, "Interrupted"),
+            ArrError::LedgerFailure(s) => write!(f, "Cleanup ledger: {}", s),
+            ArrError::InstallFailure(s) => write!(f, "Install failed: {}", s),
+            ArrError::PrerequisiteNotMet(s) => write!(f, "Prerequisite not met: {}", s),
+            ArrError::ManualStepsSkipped => write!(f, "The operator skipped the manual steps"),
+        }
+    }
+}
+
+/// A `#{variable}` in one of a test's commands that has no value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unresolved {
+    pub name: String,
+    pub phase: Phase,
+    /// One-based, for the check and dependency phases
+    pub dependency: Option<usize>,
+    /// One-based, within the command
+    pub line: usize,
+    /// One-based, counted in characters
+    pub column: usize,
+    /// The input argument it is most likely a misspelling of
+    pub closest: Option<String>,
+}
+
+impl fmt::Display for Unresolved {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.dependency {
+            Some(n) => write!(f, "{} of dependency {}", self.phase, n)?,
+            None => write!(f, "{}", self.phase)?,
+        }
+        write!(f, " {}:{}: `#{{{}}}`", self.line, self.column, self.name)?;
+
+        match &self.closest {
+            Some(closest) => write!(f, ", did you mean `{}`?", closest),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Where in a file a problem is, and what the file says there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceError {
+    pub path: PathBuf,
+    /// One-based
+    pub line: Option<usize>,
+    /// One-based, counted in characters
+    pub column: Option<usize>,
+    /// The offending line, as written
+    pub snippet: Option<String>,
+    pub message: String,
+}
+
+impl SourceError {
+    /// A problem at a one-based line and column of a file's text.
+    pub fn new(
+        path: PathBuf,
+        text: &str,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: String,
+    ) -> Self {
+        Self {
+            path,
+            line,
+            column,
+            snippet: line
+                .and_then(|l| text.lines().nth(l.saturating_sub(1)))
+                .map(String::from),
+            message,
+        }
+    }
+
+    /// A file that could not be read at all.
+    pub fn unreadable(path: PathBuf, e: &io::Error) -> Self {
+        Self::new(path, "", None, None, e.to_string())
+    }
+
+    /// Locate a `serde_yaml` failure in the text it was parsing.
+    pub fn from_yaml(path: PathBuf, text: &str, e: &serde_yaml::Error) -> Self {
+        let location = e.location();
+        let mut message = e.to_string();
+
+        // the location is reported separately
+        if let Some(l) = &location {
+            message = message.replacen(
+                &format!(" at line {} column {}", l.line(), l.column()),
+                "",
+                1,
+            );
+        }
+
+        Self::new(
+            path,
+            text,
+            location.as_ref().map(|l| l.line()),
+            location.as_ref().map(|l| l.column()),
+            message,
+        )
+    }
+
+    /// Locate a `serde_json` failure in the text it was parsing.
+    pub fn from_json(path: PathBuf, text: &str, e: &serde_json::Error) -> Self {
+        let message = e.to_string().replacen(
+            &format!(" at line {} column {}", e.line(), e.column()),
+            "",
+            1,
+        );
+
+        Self::new(path, text, Some(e.line()), Some(e.column()), message)
+    }
+
+    /// Locate a `toml` failure in the text it was parsing.
+    pub fn from_toml(path: PathBuf, text: &str, e: &toml::de::Error) -> Self {
+        let (line, column) = match e.span() {
+            Some(span) => {
+                let before = &text[..span.start.min(text.len())];
+                let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+                (
+                    Some(before.matches('\n').count() + 1),
+                    Some(before[line_start..].chars().count() + 1),
+                )
+            }
+            None => (None, None),
+        };
+
+        Self::new(path, text, line, column, e.message().to_string())
+    }
+
+    /// The error with the offending line and a caret under the column, like a compiler's.
+    pub fn diagnostic(&self) -> String {
+        let mut out = format!("error: {}\n --> {}", self.message, self.location());
+
+        if let (Some(line), Some(snippet)) = (self.line, &self.snippet) {
+            let gutter = " ".repeat(line.to_string().len());
+            // keep any tabs so the caret lines up with the snippet
+            let pad: String = snippet
+                .chars()
+                .take(self.column.unwrap_or(1).saturating_sub(1))
+                .map(|c| if c == '\t' { '\t' } else { ' ' })
+                .collect();
+
+            out.push_str(&format!(
+                "\n{gutter} |\n{line} | {snippet}\n{gutter} | {pad}^",
+                gutter = gutter,
+                line = line,
+                snippet = snippet,
+                pad = pad
+            ));
         }
+
+        out
+    }
+
+    fn location(&self) -> String {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => format!("{}:{}:{}", self.path.display(), line, column),
+            (Some(line), None) => format!("{}:{}", self.path.display(), line),
+            _ => self.path.display().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.location(), self.message)
     }
 }
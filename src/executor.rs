@@ -0,0 +1,225 @@
+use std::{ffi::OsStr, process::Command};
+
+use log::error;
+
+use crate::{error::ArrError, quote::Quoting};
+
+/// How an executor receives the script it runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The script is passed as the last argument
+    Argument,
+    /// The script is written to a temporary file with this extension, and the
+    /// file's path is passed as the last argument
+    ScriptFile(&'static str),
+    /// The steps are carried out by an operator, nothing is run
+    Manual,
+}
+
+/// Something that can run the scripts found in the atomics.
+pub trait Executor: Sync {
+    /// The executor's name in the atomics, e.g. `command_prompt`
+    fn name(&self) -> &'static str;
+
+    fn delivery(&self) -> Delivery;
+
+    /// The command that runs `script`, which is either the script itself or a
+    /// path to it, depending on the executor's delivery.
+    ///
+    /// Executors whose steps are carried out by hand have no command.
+    fn command(&self, script: &OsStr) -> Option<Command>;
+
+    /// Adjust the script before it is delivered.
+    fn render(&self, script: &str) -> String {
+        script.to_string()
+    }
+
+    /// The quoting rules variables are escaped with, if they are escaped at all.
+    fn quoting(&self) -> Option<Quoting> {
+        None
+    }
+}
+
+/// An interpreter that takes a script after a fixed set of arguments.
+pub struct Interpreter {
+    name: &'static str,
+    program: &'static str,
+    args: &'static [&'static str],
+    quoting: Quoting,
+}
+
+impl Executor for Interpreter {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery::Argument
+    }
+
+    fn command(&self, script: &OsStr) -> Option<Command> {
+        let mut command = Command::new(self.program);
+        command.args(self.args).arg(script);
+        Some(command)
+    }
+
+    fn quoting(&self) -> Option<Quoting> {
+        Some(self.quoting)
+    }
+}
+
+/// `cmd /c` only runs the first line of its argument, so it is given a batch file.
+pub struct CommandPrompt;
+
+impl Executor for CommandPrompt {
+    fn name(&self) -> &'static str {
+        "command_prompt"
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery::ScriptFile("cmd")
+    }
+
+    fn command(&self, script: &OsStr) -> Option<Command> {
+        let mut command = Command::new("cmd");
+        command.arg("/c").arg(script);
+        Some(command)
+    }
+
+    fn render(&self, script: &str) -> String {
+        script.lines().collect::<Vec<_>>().join("\r\n")
+    }
+
+    fn quoting(&self) -> Option<Quoting> {
+        Some(Quoting::Cmd)
+    }
+}
+
+/// Tests whose steps are performed by hand.
+pub struct Manual;
+
+impl Executor for Manual {
+    fn name(&self) -> &'static str {
+        "manual"
+    }
+
+    fn delivery(&self) -> Delivery {
+        Delivery::Manual
+    }
+
+    fn command(&self, _script: &OsStr) -> Option<Command> {
+        None
+    }
+}
+
+static SH: Interpreter = Interpreter {
+    name: "sh",
+    program: "sh",
+    args: &["-c"],
+    quoting: Quoting::Posix,
+};
+
+static BASH: Interpreter = Interpreter {
+    name: "bash",
+    program: "bash",
+    args: &["-c"],
+    quoting: Quoting::Posix,
+};
+
+static POWERSHELL: Interpreter = Interpreter {
+    name: "powershell",
+    program: "pwsh",
+    args: &["-NoProfile", "-Command"],
+    quoting: Quoting::PowerShell,
+};
+
+static EXECUTORS: &[&dyn Executor] = &[&SH, &BASH, &POWERSHELL, &CommandPrompt, &Manual];
+
+/// Look up the executor for a name used in the atomics.
+pub fn executor_for(name: &str) -> Result<&'static dyn Executor, ArrError> {
+    let name = name.trim();
+
+    match EXECUTORS
+        .iter()
+        .find(|executor| executor.name().eq_ignore_ascii_case(name))
+    {
+        Some(executor) => Ok(*executor),
+        None => {
+            error!("No executor is registered for `{}`", name);
+            Err(ArrError::UnknownExecutor(name.to_string()))
+        }
+    }
+}
+
+/// The names of every registered executor.
+pub fn executor_names() -> impl Iterator<Item = &'static str> {
+    EXECUTORS.iter().map(|executor| executor.name())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn command_line(name: &str, script: &str) -> Vec<String> {
+        let command = executor_for(name)
+            .unwrap()
+            .command(OsStr::new(script))
+            .unwrap();
+
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn interpreters() {
+        assert_eq!(command_line("sh", "id"), vec!["sh", "-c", "id"]);
+        assert_eq!(command_line("bash", "id"), vec!["bash", "-c", "id"]);
+        assert_eq!(
+            command_line("powershell", "whoami"),
+            vec!["pwsh", "-NoProfile", "-Command", "whoami"]
+        );
+        assert_eq!(
+            command_line("command_prompt", "C:\\script.cmd"),
+            vec!["cmd", "/c", "C:\\script.cmd"]
+        );
+    }
+
+    #[test]
+    fn delivery() {
+        assert_eq!(executor_for("sh").unwrap().delivery(), Delivery::Argument);
+        assert_eq!(
+            executor_for("command_prompt").unwrap().delivery(),
+            Delivery::ScriptFile("cmd")
+        );
+        assert_eq!(executor_for("manual").unwrap().delivery(), Delivery::Manual);
+        assert!(executor_for("manual")
+            .unwrap()
+            .command(OsStr::new("step"))
+            .is_none());
+    }
+
+    #[test]
+    fn names_are_case_insensitive() {
+        assert_eq!(executor_for("PowerShell").unwrap().name(), "powershell");
+    }
+
+    #[test]
+    fn unknown_executor() {
+        assert_eq!(
+            executor_for("zsh").err(),
+            Some(ArrError::UnknownExecutor("zsh".to_string()))
+        );
+    }
+
+    #[test]
+    fn command_prompt_uses_crlf() {
+        assert_eq!(
+            executor_for("command_prompt")
+                .unwrap()
+                .render("echo one\necho two\n"),
+            "echo one\r\necho two"
+        );
+    }
+}
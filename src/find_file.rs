@@ -1,8 +1,11 @@
 use log::{error, info};
 use walkdir::{DirEntry, WalkDir};
 
-use crate::error::ArrError;
-use std::path::{Path, PathBuf};
+use crate::error::{ArrError, SourceError};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
 
 pub fn find_file(technique: &str, art_path: &Path) -> Result<PathBuf, ArrError> {
     let search_string = format!("{}.yaml", technique);
@@ -48,9 +51,14 @@ pub fn find_atomics_dir(art_path: &Path) -> Result<PathBuf, ArrError> {
         Ok(atomics_path)
     } else {
         error!("Unable to locate `used_guids.txt`, which is used to located the atomics directory");
-        Err(ArrError::FileNotFound(
-            "Cannot locate the atomics directory".to_string(),
-        ))
+        let e = io::Error::new(
+            io::ErrorKind::NotFound,
+            "cannot locate the atomics directory, there is no used_guids.txt under it",
+        );
+        Err(ArrError::CannotReadFile(SourceError::unreadable(
+            art_path.to_owned(),
+            &e,
+        )))
     }
 }
 
@@ -84,4 +92,17 @@ mod test {
             "T1574.006.yaml"
         );
     }
+
+    #[test]
+    fn missing_atomics_dir_names_the_path() {
+        let art_path = tempfile::tempdir().unwrap();
+
+        match find_atomics_dir(art_path.path()) {
+            Err(ArrError::CannotReadFile(e)) => {
+                assert_eq!(e.path, art_path.path());
+                assert!(e.message.contains("used_guids.txt"));
+            }
+            other => panic!("expected CannotReadFile, got {:?}", other),
+        }
+    }
 }
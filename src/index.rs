@@ -0,0 +1,341 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs,
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ArrError,
+    find_file::{all_techniques, find_atomics_dir},
+    parse_yaml::parse_art_file,
+};
+
+// jobs running at the same time share one rebuild
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// What the index remembers about a test.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct IndexedTest {
+    pub guid: String,
+    pub name: String,
+    pub platforms: Vec<String>,
+    pub executor: String,
+    pub dependency_executor: Option<String>,
+}
+
+/// A technique's YAML file, as it was when it was last parsed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct IndexedFile {
+    pub path: PathBuf,
+    pub modified: SystemTime,
+    /// The file's name without `.yaml`, which is how techniques are looked up
+    pub technique: String,
+    /// Empty if the file could not be parsed
+    pub tests: Vec<IndexedTest>,
+}
+
+/// Every technique under an ART path, kept on disk so commands don't walk and parse the tree.
+///
+/// The index is rebuilt whenever a technique file, or a directory holding one, changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct Index {
+    art_path: PathBuf,
+    atomics_dir: PathBuf,
+    /// Adding or removing a technique changes the modified time of these
+    dirs: Vec<(PathBuf, SystemTime)>,
+    files: Vec<IndexedFile>,
+}
+
+impl Index {
+    /// The index for the path, cached in `cache_dir`, or built from scratch without one.
+    pub fn open(art_path: &Path, cache_dir: Option<&Path>) -> Result<Self, ArrError> {
+        match cache_dir {
+            Some(cache_dir) => Self::load(art_path, &Self::cache_path(art_path, cache_dir)),
+            None => Self::build(art_path, None),
+        }
+    }
+
+    /// `<cache_dir>/<hash of the path>.json`
+    fn cache_path(art_path: &Path, cache_dir: &Path) -> PathBuf {
+        let art_path = art_path
+            .canonicalize()
+            .unwrap_or_else(|_| art_path.to_owned());
+
+        let mut hasher = DefaultHasher::new();
+        art_path.hash(&mut hasher);
+
+        cache_dir.join(format!("{:016x}.json", hasher.finish()))
+    }
+
+    /// Read the cached index, bringing it up to date if anything changed.
+    pub fn load(art_path: &Path, cache: &Path) -> Result<Self, ArrError> {
+        let _lock = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let cached = fs::read_to_string(cache)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Index>(&json).ok())
+            .filter(|index| index.art_path == art_path);
+
+        if let Some(index) = &cached {
+            if index.is_fresh() {
+                info!("Using the technique index at {}", cache.to_string_lossy());
+                return Ok(index.clone());
+            }
+        }
+
+        info!("Indexing the techniques in {}", art_path.to_string_lossy());
+        let index = Self::build(art_path, cached.as_ref())?;
+
+        // a cache we can't write only costs time
+        if let Err(e) = index.save(cache) {
+            error!(
+                "Unable to save the technique index to {}: {}",
+                cache.to_string_lossy(),
+                e
+            );
+        }
+        if let Some(dir) = cache.parent() {
+            prune(dir);
+        }
+
+        Ok(index)
+    }
+
+    pub fn atomics_dir(&self) -> &Path {
+        &self.atomics_dir
+    }
+
+    pub fn files(&self) -> &[IndexedFile] {
+        &self.files
+    }
+
+    /// The YAML file for a technique, like `find_file` without the walk.
+    pub fn technique_file(&self, technique: &str) -> Option<&Path> {
+        self.files
+            .iter()
+            .find(|f| f.technique.eq_ignore_ascii_case(technique))
+            .map(|f| f.path.as_path())
+    }
+
+    fn build(art_path: &Path, cached: Option<&Index>) -> Result<Self, ArrError> {
+        let atomics_dir = find_atomics_dir(art_path)?;
+
+        let mut dirs = vec![atomics_dir.clone()];
+        let mut files = Vec::new();
+
+        for entry in all_techniques(art_path) {
+            let path = entry.into_path();
+            let modified = modified(&path);
+
+            if let Some(parent) = path.parent() {
+                if !dirs.iter().any(|d| d == parent) {
+                    dirs.push(parent.to_owned());
+                }
+            }
+
+            // only parse the files that changed
+            let unchanged = cached
+                .and_then(|index| index.files.iter().find(|f| f.path == path))
+                .filter(|f| modified.is_some() && Some(f.modified) == modified);
+
+            match unchanged {
+                Some(f) => files.push(f.clone()),
+                None => files.push(index_file(path, modified.unwrap_or(SystemTime::UNIX_EPOCH))),
+            }
+        }
+
+        let dirs = dirs
+            .into_iter()
+            .filter_map(|d| modified(&d).map(|m| (d, m)))
+            .collect();
+
+        Ok(Self {
+            art_path: art_path.to_owned(),
+            atomics_dir,
+            dirs,
+            files,
+        })
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.dirs
+            .iter()
+            .map(|(path, time)| (path, time))
+            .chain(self.files.iter().map(|f| (&f.path, &f.modified)))
+            .all(|(path, time)| modified(path).as_ref() == Some(time))
+    }
+
+    fn save(&self, cache: &Path) -> Result<(), String> {
+        let dir = cache.parent().unwrap_or_else(|| Path::new("."));
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+
+        let json = serde_json::to_string(self).map_err(|e| e.to_string())?;
+
+        // write to the side and rename, so a reader never sees half an index
+        let tmp = tempfile::NamedTempFile::new_in(dir).map_err(|e| e.to_string())?;
+        fs::write(tmp.path(), json).map_err(|e| e.to_string())?;
+        tmp.persist(cache).map_err(|e| e.to_string())?;
+
+        Ok(())
+    }
+}
+
+/// Remove the cached indexes of ART paths that no longer exist.
+fn prune(cache_dir: &Path) {
+    // only the path is needed to tell whether an index is stale
+    #[derive(Deserialize)]
+    struct Cached {
+        art_path: PathBuf,
+    }
+
+    let entries = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries.flatten(),
+        Err(_) => return,
+    };
+
+    for entry in entries {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+
+        let stale = fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str::<Cached>(&json).ok())
+            .is_some_and(|cached| !cached.art_path.exists());
+
+        if stale {
+            info!(
+                "Removing the stale technique index {}",
+                path.to_string_lossy()
+            );
+            if let Err(e) = fs::remove_file(&path) {
+                error!("Unable to remove {}: {}", path.to_string_lossy(), e);
+            }
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn index_file(path: PathBuf, modified: SystemTime) -> IndexedFile {
+    let technique = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let tests = match parse_art_file(&path) {
+        Ok(yaml) => yaml
+            .atomic_tests
+            .into_iter()
+            .map(|test| IndexedTest {
+                guid: test.auto_generated_guid,
+                name: test.name,
+                platforms: test.supported_platforms,
+                executor: test.executor.name,
+                dependency_executor: test.dependency_executor_name,
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+
+    IndexedFile {
+        path,
+        modified,
+        technique,
+        tests,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{corpus, technique_file, write_atomics};
+    use std::time::Duration;
+
+    fn technique(name: &str) -> String {
+        format!(
+            "attack_technique: T0000.005
+display_name: Index
+atomic_tests:
+- name: {}
+  auto_generated_guid: 00000000-0000-0000-0000-000000000050
+  description: Indexed
+  supported_platforms:
+  - linux
+  executor:
+    command: id
+    name: sh
+",
+            name
+        )
+    }
+
+    // some filesystems only keep whole seconds
+    fn touch(path: &Path, contents: &str) {
+        let before = modified(path);
+        loop {
+            fs::write(path, contents).unwrap();
+            if modified(path) != before {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn rebuilds_when_files_change() {
+        let art_path = corpus("T0000.005", &technique("Before"));
+        let atomics = art_path.path().join("atomics");
+        let yaml = technique_file(&atomics, "T0000.005");
+
+        let state = tempfile::tempdir().unwrap();
+        let cache = state.path().join("index/index.json");
+
+        let index = Index::load(art_path.path(), &cache).unwrap();
+        assert!(cache.exists());
+        assert_eq!(index.atomics_dir(), atomics);
+        assert_eq!(index.technique_file("t0000.005"), Some(yaml.as_path()));
+
+        // unchanged files come straight from the cache
+        assert_eq!(Index::load(art_path.path(), &cache).unwrap(), index);
+
+        touch(&yaml, &technique("After"));
+        let index = Index::load(art_path.path(), &cache).unwrap();
+        assert_eq!(index.files()[0].tests[0].name, "After");
+
+        // new techniques are picked up too
+        write_atomics(art_path.path(), &[("T0000.006", &technique("Added"))], "");
+        let added = technique_file(&atomics, "T0000.006");
+
+        let index = Index::load(art_path.path(), &cache).unwrap();
+        assert_eq!(index.technique_file("T0000.006"), Some(added.as_path()));
+    }
+
+    #[test]
+    fn stale_indexes_are_pruned() {
+        let state = tempfile::tempdir().unwrap();
+        let cache_dir = state.path().join("index");
+
+        let removed = corpus("T0000.005", &technique("Pruned"));
+        let kept = corpus("T0000.005", &technique("Pruned"));
+
+        Index::open(removed.path(), Some(&cache_dir)).unwrap();
+        let stale = Index::cache_path(removed.path(), &cache_dir);
+        assert!(stale.exists());
+        drop(removed);
+
+        // building another index clears out the one whose tree is gone
+        Index::open(kept.path(), Some(&cache_dir)).unwrap();
+        assert!(!stale.exists());
+        assert!(Index::cache_path(kept.path(), &cache_dir).exists());
+    }
+}
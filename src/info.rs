@@ -0,0 +1,273 @@
+use std::{collections::HashMap, fmt};
+
+use serde::Serialize;
+
+use crate::{
+    argument::ArgType,
+    dependency_executor, gather_args, parse_commands,
+    parse_yaml::{AtomicReadTeamTechnique, AtomicTest},
+    values::{ValueSource, VarSources},
+    vars_for, ArrError,
+};
+
+/// A command as written in the YAML, and as it would run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CommandInfo {
+    pub raw: String,
+    /// The command after `PathToAtomicsFolder` and `#{}` substitution, unless that failed
+    pub substituted: Option<String>,
+    /// Why the command could not be substituted
+    pub error: Option<String>,
+}
+
+impl CommandInfo {
+    fn new(raw: &str, substituted: Result<String, ArrError>) -> Self {
+        let (substituted, error) = match substituted {
+            Ok(substituted) => (Some(substituted), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        Self {
+            raw: raw.to_string(),
+            substituted,
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ArgumentInfo {
+    pub name: String,
+    pub description: String,
+    pub arg_type: ArgType,
+    pub default: String,
+    /// The value the test would use, after the user's values and path substitution
+    pub value: String,
+    pub source: ValueSource,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DependencyInfo {
+    pub description: String,
+    pub check: CommandInfo,
+    pub get: CommandInfo,
+}
+
+/// Everything the YAML says about one test.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TestInfo {
+    pub test_number: usize,
+    pub name: String,
+    pub guid: String,
+    pub description: String,
+    pub platforms: Vec<String>,
+    pub executor: String,
+    pub elevation_required: bool,
+    pub input_arguments: Vec<ArgumentInfo>,
+    pub dependency_executor: Option<String>,
+    pub dependencies: Vec<DependencyInfo>,
+    /// The attack's command, or its steps for manual tests
+    pub attack: CommandInfo,
+    pub cleanup: Option<CommandInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TechniqueInfo {
+    pub technique: String,
+    pub display_name: String,
+    pub tests: Vec<TestInfo>,
+}
+
+impl TechniqueInfo {
+    /// Describe the tests at the given zero-based positions.
+    pub(crate) fn new(
+        yaml: &AtomicReadTeamTechnique,
+        test_nums: &[usize],
+        vars: &HashMap<String, String>,
+        var_sources: &VarSources,
+        raw_vars: bool,
+    ) -> Result<Self, ArrError> {
+        let tests = test_nums
+            .iter()
+            .filter_map(|&i| yaml.atomic_tests.get(i).map(|test| (i, test)))
+            .map(|(i, test)| {
+                TestInfo::new(
+                    &yaml.attack_technique,
+                    i,
+                    test,
+                    vars_for(test, vars),
+                    var_sources,
+                    raw_vars,
+                )
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            technique: yaml.attack_technique.clone(),
+            display_name: yaml.display_name.clone(),
+            tests,
+        })
+    }
+}
+
+impl TestInfo {
+    fn new(
+        technique: &str,
+        test_num: usize,
+        test: &AtomicTest,
+        vars: HashMap<String, String>,
+        var_sources: &VarSources,
+        raw_vars: bool,
+    ) -> Result<Self, ArrError> {
+        let args = gather_args(technique, test, vars, var_sources, raw_vars)?;
+
+        let command = |raw: &str, executor: &str| {
+            CommandInfo::new(raw, parse_commands(raw, &args, Some(executor)))
+        };
+        let dependency_executor = dependency_executor(test);
+
+        let mut input_arguments: Vec<ArgumentInfo> = test
+            .input_arguments
+            .iter()
+            .map(|(name, arg)| ArgumentInfo {
+                name: name.clone(),
+                description: arg.description.trim().to_string(),
+                arg_type: arg.arg_type.clone(),
+                default: arg.default.clone(),
+                value: args.values.get(name).cloned().unwrap_or_default(),
+                source: args
+                    .sources
+                    .get(name)
+                    .copied()
+                    .unwrap_or(ValueSource::Default),
+            })
+            .collect();
+        input_arguments.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let dependencies = test
+            .dependencies
+            .iter()
+            .flatten()
+            .map(|d| DependencyInfo {
+                description: d.description.trim().to_string(),
+                check: command(&d.prereq_command, dependency_executor),
+                get: command(&d.get_prereq_command, dependency_executor),
+            })
+            .collect();
+
+        let attack = test
+            .executor
+            .command
+            .as_ref()
+            .or(test.executor.steps.as_ref())
+            .map(|c| command(c, &test.executor.name))
+            .unwrap_or_else(|| command("", &test.executor.name));
+
+        Ok(Self {
+            test_number: test_num + 1,
+            name: test.name.clone(),
+            guid: test.auto_generated_guid.clone(),
+            description: test.description.trim().to_string(),
+            platforms: test.supported_platforms.clone(),
+            executor: test.executor.name.clone(),
+            elevation_required: test.executor.elevation_required.unwrap_or(false),
+            input_arguments,
+            dependency_executor: test.dependency_executor_name.clone(),
+            dependencies,
+            attack,
+            cleanup: test
+                .executor
+                .cleanup_command
+                .as_deref()
+                .map(|c| command(c, &test.executor.name)),
+        })
+    }
+}
+
+fn write_block(f: &mut fmt::Formatter, indent: &str, text: &str) -> fmt::Result {
+    for line in text.trim_end().lines() {
+        match line.is_empty() {
+            true => writeln!(f, "{}", indent.trim_end())?,
+            false => writeln!(f, "{}{}", indent, line)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_command(f: &mut fmt::Formatter, command: &CommandInfo) -> fmt::Result {
+    writeln!(f, "    as written:")?;
+    write_block(f, "    | ", &command.raw)?;
+    match (&command.substituted, &command.error) {
+        (Some(substituted), _) => {
+            writeln!(f, "    as run:")?;
+            write_block(f, "    | ", substituted)
+        }
+        (None, Some(e)) => {
+            writeln!(f, "    cannot substitute:")?;
+            write_block(f, "    ", e)
+        }
+        (None, None) => Ok(()),
+    }
+}
+
+impl fmt::Display for TestInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Test {}: {}", self.test_number, self.name)?;
+        writeln!(f, "GUID: {}", self.guid)?;
+        writeln!(f, "Platforms: {}", self.platforms.join(", "))?;
+        match self.elevation_required {
+            true => writeln!(f, "Executor: {} (elevation required)", self.executor)?,
+            false => writeln!(f, "Executor: {}", self.executor)?,
+        }
+
+        writeln!(f, "\nDescription:")?;
+        write_block(f, "    ", &self.description)?;
+
+        writeln!(f, "\nInput Arguments:")?;
+        if self.input_arguments.is_empty() {
+            writeln!(f, "    (None)")?;
+        }
+        for arg in &self.input_arguments {
+            writeln!(f, "    {} ({})", arg.name, arg.arg_type)?;
+            write_block(f, "        ", &arg.description)?;
+            writeln!(f, "        default: {}", arg.default)?;
+            if arg.value != arg.default {
+                writeln!(f, "        value: {} ({})", arg.value, arg.source)?;
+            }
+        }
+
+        for (i, dependency) in self.dependencies.iter().enumerate() {
+            writeln!(f, "\nDependency {}: {}", i + 1, dependency.description)?;
+            if let Some(executor) = &self.dependency_executor {
+                writeln!(f, "  executor: {}", executor)?;
+            }
+            writeln!(f, "  check:")?;
+            write_command(f, &dependency.check)?;
+            writeln!(f, "  get:")?;
+            write_command(f, &dependency.get)?;
+        }
+
+        writeln!(f, "\nAttack:")?;
+        write_command(f, &self.attack)?;
+
+        if let Some(cleanup) = &self.cleanup {
+            writeln!(f, "\nCleanup:")?;
+            write_command(f, cleanup)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for TechniqueInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}: {}", self.technique, self.display_name)?;
+
+        for test in &self.tests {
+            writeln!(f)?;
+            write!(f, "{}", test)?;
+        }
+
+        Ok(())
+    }
+}
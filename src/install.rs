@@ -0,0 +1,698 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    error::ArrError,
+    find_file::{all_techniques, find_atomics_dir},
+    parse_yaml::parse_art_file,
+};
+
+/// The name of the lockfile kept at the top of an installed corpus.
+pub const LOCKFILE: &str = "arr.lock";
+
+/// Where a corpus is installed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceKind {
+    /// A `.tar` or `.tar.gz` archive
+    Tarball,
+    Zip,
+    /// A local clone or bare mirror of a git repository
+    Git,
+}
+
+impl fmt::Display for SourceKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SourceKind::Tarball => write!(f, "tarball"),
+            SourceKind::Zip => write!(f, "zip"),
+            SourceKind::Git => write!(f, "git"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    pub kind: SourceKind,
+    pub path: PathBuf,
+    /// The branch, tag or commit to check out of a git mirror
+    pub reference: Option<String>,
+}
+
+impl Source {
+    /// Work out what kind of source the path is: directories are git mirrors, and
+    /// archives are told apart by their first bytes.
+    pub fn detect(path: &Path, reference: Option<String>) -> Result<Self, ArrError> {
+        let err = |e: String| ArrError::InstallFailure(format!("{}: {}", path.display(), e));
+
+        let kind = match path.is_dir() {
+            true => SourceKind::Git,
+            false => {
+                let mut magic = [0u8; 4];
+                let read = File::open(path)
+                    .and_then(|mut f| f.read(&mut magic))
+                    .map_err(|e| err(e.to_string()))?;
+
+                match &magic[..read] {
+                    [b'P', b'K', 3, 4] => SourceKind::Zip,
+                    _ => SourceKind::Tarball,
+                }
+            }
+        };
+
+        // the lockfile must still find the source from another directory
+        Self {
+            kind,
+            path: path.canonicalize().unwrap_or_else(|_| path.to_owned()),
+            reference: None,
+        }
+        .with_reference(reference)
+    }
+
+    /// Check out `reference` instead, if one is given, e.g. to update a locked source.
+    pub fn with_reference(mut self, reference: Option<String>) -> Result<Self, ArrError> {
+        if reference.is_none() {
+            return Ok(self);
+        }
+
+        if self.kind != SourceKind::Git {
+            return Err(ArrError::InstallFailure(format!(
+                "{}: only git mirrors can check out a reference",
+                self.path.display()
+            )));
+        }
+
+        self.reference = reference;
+        Ok(self)
+    }
+}
+
+/// The version of the corpus that is installed, and where it came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub source: Source,
+    /// The commit of a git mirror, or the SHA-256 of an archive
+    pub version: String,
+    /// Seconds since the Unix epoch
+    pub installed: u64,
+}
+
+impl Lockfile {
+    pub fn read(dest: &Path) -> Result<Option<Self>, ArrError> {
+        let path = dest.join(LOCKFILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let err = |e: String| ArrError::InstallFailure(format!("{}: {}", path.display(), e));
+        let contents = fs::read_to_string(&path).map_err(|e| err(e.to_string()))?;
+
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| err(e.to_string()))
+    }
+
+    fn write(&self, dest: &Path) -> Result<(), ArrError> {
+        let path = dest.join(LOCKFILE);
+        let err = |e: String| ArrError::InstallFailure(format!("{}: {}", path.display(), e));
+
+        let json = serde_json::to_string_pretty(self).map_err(|e| err(e.to_string()))?;
+        fs::write(&path, json).map_err(|e| err(e.to_string()))
+    }
+}
+
+/// What an install changed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InstallReport {
+    pub version: String,
+    pub previous_version: Option<String>,
+    /// The copy that was replaced, kept until the next update
+    pub rollback: Option<PathBuf>,
+    pub added: Vec<String>,
+    pub changed: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Install a corpus into a directory that doesn't have one yet.
+pub fn get(source: &Source, dest: &Path) -> Result<InstallReport, ArrError> {
+    if dest.exists() && fs::read_dir(dest).map_or(true, |mut d| d.next().is_some()) {
+        error!("{} already exists", dest.display());
+        return Err(ArrError::InstallFailure(format!(
+            "{} already exists, use `update` to refresh it",
+            dest.display()
+        )));
+    }
+
+    install(source, dest)
+}
+
+/// Replace an installed corpus, keeping the old one for `rollback`.
+pub fn update(source: &Source, dest: &Path) -> Result<InstallReport, ArrError> {
+    if !dest.exists() {
+        error!("{} does not exist", dest.display());
+        return Err(ArrError::InstallFailure(format!(
+            "{} does not exist, use `get` to install it",
+            dest.display()
+        )));
+    }
+
+    install(source, dest)
+}
+
+/// Put back the copy the last update replaced.
+pub fn rollback(dest: &Path) -> Result<InstallReport, ArrError> {
+    let previous = sibling(dest, ".previous");
+    if !previous.is_dir() {
+        return Err(ArrError::InstallFailure(format!(
+            "There is no previous copy at {}",
+            previous.display()
+        )));
+    }
+
+    let report = InstallReport {
+        version: Lockfile::read(&previous)?
+            .map(|l| l.version)
+            .unwrap_or_default(),
+        previous_version: Lockfile::read(dest)?.map(|l| l.version),
+        rollback: None,
+        ..diff(dest, &previous)
+    };
+
+    // swap the two copies, so rolling back twice undoes the rollback
+    let staging = sibling(dest, ".rollback");
+    rename(dest, &staging)?;
+    rename(&previous, dest)?;
+    rename(&staging, &previous)?;
+
+    info!("Rolled {} back to {}", dest.display(), &report.version);
+
+    Ok(report)
+}
+
+fn install(source: &Source, dest: &Path) -> Result<InstallReport, ArrError> {
+    let err = |e: String| ArrError::InstallFailure(e);
+
+    // stage next to the destination, so moving it into place is a rename
+    let parent = match dest.parent() {
+        Some(p) if !p.as_os_str().is_empty() => p.to_owned(),
+        _ => PathBuf::from("."),
+    };
+    fs::create_dir_all(&parent).map_err(|e| err(e.to_string()))?;
+
+    let staging = tempfile::Builder::new()
+        .prefix(".arr-staging")
+        .tempdir_in(&parent)
+        .map_err(|e| err(e.to_string()))?;
+    let content = staging.path().join("content");
+
+    info!(
+        "Installing the {} at {} into {}",
+        source.kind,
+        source.path.display(),
+        dest.display()
+    );
+
+    let version = match source.kind {
+        SourceKind::Tarball => unpack_tarball(&source.path, &content)?,
+        SourceKind::Zip => unpack_zip(&source.path, &content)?,
+        SourceKind::Git => clone(&source.path, source.reference.as_deref(), &content)?,
+    };
+
+    let root = top_level(&content);
+    verify(&root)?;
+
+    let previous_version = Lockfile::read(dest)?.map(|l| l.version);
+    let changes = diff(dest, &root);
+
+    Lockfile {
+        source: source.clone(),
+        version: version.clone(),
+        installed: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default(),
+    }
+    .write(&root)?;
+
+    // keep the copy being replaced, dropping the one before it
+    let rollback = match dest.exists() {
+        true => {
+            let previous = sibling(dest, ".previous");
+            if previous.exists() {
+                fs::remove_dir_all(&previous).map_err(|e| err(e.to_string()))?;
+            }
+            rename(dest, &previous)?;
+            Some(previous)
+        }
+        false => None,
+    };
+
+    if let Err(e) = rename(&root, dest) {
+        if let Some(previous) = &rollback {
+            let _ = fs::rename(previous, dest);
+        }
+        return Err(e);
+    }
+
+    info!("Installed {} into {}", &version, dest.display());
+
+    Ok(InstallReport {
+        version,
+        previous_version,
+        rollback,
+        ..changes
+    })
+}
+
+/// `atomic-red-team` is kept as `atomic-red-team.previous`.
+fn sibling(dest: &Path, suffix: &str) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    dest.with_file_name(name)
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), ArrError> {
+    fs::rename(from, to).map_err(|e| {
+        ArrError::InstallFailure(format!(
+            "Cannot move {} to {}: {}",
+            from.display(),
+            to.display(),
+            e
+        ))
+    })
+}
+
+fn sha256(path: &Path) -> Result<String, ArrError> {
+    let mut file = File::open(path)
+        .map_err(|e| ArrError::InstallFailure(format!("{}: {}", path.display(), e)))?;
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .map_err(|e| ArrError::InstallFailure(format!("{}: {}", path.display(), e)))?;
+
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+
+    Ok(format!("sha256:{}", digest))
+}
+
+fn unpack_tarball(path: &Path, dest: &Path) -> Result<String, ArrError> {
+    let err = |e: std::io::Error| ArrError::InstallFailure(format!("{}: {}", path.display(), e));
+
+    let mut magic = [0u8; 2];
+    let gzipped = File::open(path)
+        .and_then(|mut f| f.read(&mut magic))
+        .map_err(err)?
+        == 2
+        && magic == [0x1f, 0x8b];
+
+    let file = File::open(path).map_err(err)?;
+    let reader: Box<dyn Read> = match gzipped {
+        true => Box::new(flate2::read::GzDecoder::new(file)),
+        false => Box::new(file),
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    // fresh modified times, so cached indexes see every file as changed
+    archive.set_preserve_mtime(false);
+    archive.unpack(dest).map_err(err)?;
+
+    sha256(path)
+}
+
+fn unpack_zip(path: &Path, dest: &Path) -> Result<String, ArrError> {
+    let err = |e: String| ArrError::InstallFailure(format!("{}: {}", path.display(), e));
+
+    let file = File::open(path).map_err(|e| err(e.to_string()))?;
+    zip::ZipArchive::new(file)
+        .and_then(|mut archive| archive.extract(dest))
+        .map_err(|e| err(e.to_string()))?;
+
+    sha256(path)
+}
+
+fn clone(mirror: &Path, reference: Option<&str>, dest: &Path) -> Result<String, ArrError> {
+    let git = |args: &[&std::ffi::OsStr]| -> Result<String, ArrError> {
+        let output = Command::new("git")
+            .args(args)
+            .output()
+            .map_err(|e| ArrError::InstallFailure(format!("Cannot run git: {}", e)))?;
+
+        match output.status.success() {
+            true => Ok(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+            false => Err(ArrError::InstallFailure(format!(
+                "git {}: {}",
+                args.iter()
+                    .map(|a| a.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    };
+
+    git(&[
+        "clone".as_ref(),
+        "--quiet".as_ref(),
+        mirror.as_ref(),
+        dest.as_ref(),
+    ])?;
+
+    if let Some(reference) = reference {
+        git(&[
+            "-C".as_ref(),
+            dest.as_ref(),
+            "checkout".as_ref(),
+            "--quiet".as_ref(),
+            reference.as_ref(),
+        ])?;
+    }
+
+    let commit = git(&[
+        "-C".as_ref(),
+        dest.as_ref(),
+        "rev-parse".as_ref(),
+        "HEAD".as_ref(),
+    ])?;
+
+    // the installed copy is a plain tree, the mirror keeps the history
+    fs::remove_dir_all(dest.join(".git"))
+        .map_err(|e| ArrError::InstallFailure(format!("{}: {}", dest.display(), e)))?;
+
+    Ok(commit)
+}
+
+/// Archives usually wrap the corpus in one directory, like `atomic-red-team-master/`.
+fn top_level(content: &Path) -> PathBuf {
+    let entries: Vec<PathBuf> = fs::read_dir(content)
+        .map(|d| d.filter_map(Result::ok).map(|e| e.path()).collect())
+        .unwrap_or_default();
+
+    match entries.as_slice() {
+        [only] if only.is_dir() && !only.ends_with("atomics") => only.clone(),
+        _ => content.to_owned(),
+    }
+}
+
+/// Make sure the corpus has a `used_guids.txt` that lists every test's GUID.
+fn verify(root: &Path) -> Result<(), ArrError> {
+    let atomics_dir = find_atomics_dir(root)
+        .map_err(|_| ArrError::InstallFailure("The corpus has no used_guids.txt".to_string()))?;
+
+    let used_guids: HashSet<String> = fs::read_to_string(atomics_dir.join("used_guids.txt"))
+        .map_err(|e| ArrError::InstallFailure(format!("Cannot read used_guids.txt: {}", e)))?
+        .lines()
+        .map(|l| l.trim().to_lowercase())
+        .filter(|l| !l.is_empty())
+        .collect();
+
+    let mut missing = Vec::new();
+    for f in all_techniques(root) {
+        let technique = parse_art_file(f.path())
+            .map_err(|e| ArrError::InstallFailure(format!("{}: {}", f.path().display(), e)))?;
+
+        for test in technique.atomic_tests {
+            if !used_guids.contains(&test.auto_generated_guid.to_lowercase()) {
+                missing.push(format!(
+                    "{} ({})",
+                    test.auto_generated_guid, technique.attack_technique
+                ));
+            }
+        }
+    }
+
+    match missing.is_empty() {
+        true => Ok(()),
+        false => {
+            error!("used_guids.txt is missing {} GUID(s)", missing.len());
+            Err(ArrError::InstallFailure(format!(
+                "used_guids.txt does not list: {}",
+                missing.join(", ")
+            )))
+        }
+    }
+}
+
+/// Each technique's YAML, by technique ID.
+fn technique_files(root: &Path) -> BTreeMap<String, Vec<u8>> {
+    all_techniques(root)
+        .filter_map(|f| {
+            let id = f.path().file_stem()?.to_string_lossy().to_uppercase();
+            Some((id, fs::read(f.path()).ok()?))
+        })
+        .collect()
+}
+
+fn diff(old: &Path, new: &Path) -> InstallReport {
+    let old = match old.exists() {
+        true => technique_files(old),
+        false => BTreeMap::new(),
+    };
+    let new = technique_files(new);
+
+    InstallReport {
+        version: String::new(),
+        previous_version: None,
+        rollback: None,
+        added: new
+            .keys()
+            .filter(|t| !old.contains_key(*t))
+            .cloned()
+            .collect(),
+        changed: new
+            .iter()
+            .filter(|(t, yaml)| old.get(*t).is_some_and(|old| old != *yaml))
+            .map(|(t, _)| t.clone())
+            .collect(),
+        removed: old
+            .keys()
+            .filter(|t| !new.contains_key(*t))
+            .cloned()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::write_atomics;
+    use std::io::{self, Write};
+
+    fn technique(id: &str, guid: &str, command: &str) -> String {
+        format!(
+            "attack_technique: {}
+display_name: Install
+atomic_tests:
+- name: Installed
+  auto_generated_guid: {}
+  description: Installed from an archive
+  supported_platforms:
+  - linux
+  executor:
+    command: {}
+    name: sh
+",
+            id, guid, command
+        )
+    }
+
+    /// A corpus wrapped in `atomic-red-team-master/`, as GitHub archives are.
+    fn corpus(techniques: &[(&str, &str, &str)], used_guids: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let yamls: Vec<_> = techniques
+            .iter()
+            .map(|(id, guid, command)| (*id, technique(id, guid, command)))
+            .collect();
+        let yamls: Vec<_> = yamls
+            .iter()
+            .map(|(id, yaml)| (*id, yaml.as_str()))
+            .collect();
+
+        write_atomics(
+            &dir.path().join("atomic-red-team-master"),
+            &yamls,
+            used_guids,
+        );
+
+        dir
+    }
+
+    fn tarball(corpus: &Path, archive: &Path) {
+        let file = File::create(archive).unwrap();
+        let mut builder =
+            tar::Builder::new(flate2::write::GzEncoder::new(file, Default::default()));
+        builder
+            .append_dir_all(
+                "atomic-red-team-master",
+                corpus.join("atomic-red-team-master"),
+            )
+            .unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    const GUIDS: &str = "00000000-0000-0000-0000-000000000091
+00000000-0000-0000-0000-000000000092
+00000000-0000-0000-0000-000000000093
+";
+
+    #[test]
+    fn get_update_and_rollback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("atomic-red-team");
+
+        let v1 = corpus(
+            &[
+                ("T0000.091", "00000000-0000-0000-0000-000000000091", "id"),
+                ("T0000.092", "00000000-0000-0000-0000-000000000092", "id"),
+            ],
+            GUIDS,
+        );
+        tarball(v1.path(), &tmp.path().join("v1.tar.gz"));
+
+        let source = Source::detect(&tmp.path().join("v1.tar.gz"), None).unwrap();
+        assert_eq!(source.kind, SourceKind::Tarball);
+        assert!(source
+            .clone()
+            .with_reference(Some("v1".to_string()))
+            .is_err());
+
+        let report = get(&source, &dest).unwrap();
+        assert_eq!(report.added, vec!["T0000.091", "T0000.092"]);
+        assert!(dest.join("atomics/used_guids.txt").exists());
+        assert_eq!(
+            Lockfile::read(&dest).unwrap().unwrap().version,
+            report.version
+        );
+        assert!(report.version.starts_with("sha256:"));
+
+        // a second get would clobber the install
+        assert!(get(&source, &dest).is_err());
+
+        let v2 = corpus(
+            &[
+                (
+                    "T0000.092",
+                    "00000000-0000-0000-0000-000000000092",
+                    "whoami",
+                ),
+                ("T0000.093", "00000000-0000-0000-0000-000000000093", "id"),
+            ],
+            GUIDS,
+        );
+        tarball(v2.path(), &tmp.path().join("v2.tar.gz"));
+
+        let source = Source::detect(&tmp.path().join("v2.tar.gz"), None).unwrap();
+        let update = update(&source, &dest).unwrap();
+
+        assert_eq!(update.added, vec!["T0000.093"]);
+        assert_eq!(update.changed, vec!["T0000.092"]);
+        assert_eq!(update.removed, vec!["T0000.091"]);
+        assert_eq!(update.previous_version, Some(report.version.clone()));
+
+        let previous = update.rollback.unwrap();
+        assert!(previous.join("atomics/T0000.091/T0000.091.yaml").exists());
+        assert!(!dest.join("atomics/T0000.091").exists());
+
+        let rolled_back = rollback(&dest).unwrap();
+        assert_eq!(rolled_back.version, report.version);
+        assert_eq!(rolled_back.added, vec!["T0000.091"]);
+        assert!(dest.join("atomics/T0000.091/T0000.091.yaml").exists());
+    }
+
+    #[test]
+    fn unlisted_guids_are_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let corpus = corpus(
+            &[("T0000.094", "00000000-0000-0000-0000-000000000094", "id")],
+            GUIDS,
+        );
+        tarball(corpus.path(), &tmp.path().join("corpus.tar.gz"));
+
+        let source = Source::detect(&tmp.path().join("corpus.tar.gz"), None).unwrap();
+        let dest = tmp.path().join("atomic-red-team");
+
+        assert!(matches!(
+            get(&source, &dest),
+            Err(ArrError::InstallFailure(e)) if e.contains("00000000-0000-0000-0000-000000000094")
+        ));
+        assert!(!dest.exists());
+    }
+
+    #[test]
+    fn git_mirror() {
+        let corpus = corpus(
+            &[("T0000.091", "00000000-0000-0000-0000-000000000091", "id")],
+            GUIDS,
+        );
+        let mirror = corpus.path().join("atomic-red-team-master");
+
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(["-c", "user.name=arr", "-c", "user.email=arr@example.com"])
+                .args(args)
+                .current_dir(&mirror)
+                .output()
+                .map(|o| o.status.success())
+                .unwrap_or(false)
+        };
+        if !git(&["init", "--quiet"]) {
+            // the test harness captures `eprintln!`, but not stderr itself
+            let _ = writeln!(io::stderr(), "Skipping git_mirror: git is not available");
+            return;
+        }
+        assert!(git(&["add", "."]));
+        assert!(git(&["commit", "--quiet", "-m", "atomics"]));
+        assert!(git(&["tag", "v1"]));
+
+        write_atomics(
+            &mirror,
+            &[(
+                "T0000.092",
+                &technique("T0000.092", "00000000-0000-0000-0000-000000000092", "id"),
+            )],
+            GUIDS,
+        );
+        assert!(git(&["add", "."]));
+        assert!(git(&["commit", "--quiet", "-m", "more atomics"]));
+        assert!(git(&["tag", "v2"]));
+
+        let source = Source::detect(&mirror, Some("v1".to_string())).unwrap();
+        assert_eq!(source.kind, SourceKind::Git);
+
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("atomic-red-team");
+        let report = get(&source, &dest).unwrap();
+
+        assert_eq!(report.added, vec!["T0000.091"]);
+        assert_eq!(report.version.len(), 40);
+        assert!(!dest.join(".git").exists());
+
+        // updating the locked source checks out the new reference
+        let locked = Lockfile::read(&dest).unwrap().unwrap().source;
+        let update = update(
+            &locked.with_reference(Some("v2".to_string())).unwrap(),
+            &dest,
+        )
+        .unwrap();
+
+        assert_eq!(update.added, vec!["T0000.092"]);
+        assert_eq!(
+            Lockfile::read(&dest).unwrap().unwrap().source.reference,
+            Some("v2".to_string())
+        );
+    }
+}
@@ -0,0 +1,305 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ArrError;
+
+// tests running at the same time must not overwrite each other's entries,
+// other arr processes are kept out by a lock on the ledger's lock file
+static LEDGER_LOCK: Mutex<()> = Mutex::new(());
+
+/// Exclusive use of the ledger until dropped.
+struct LedgerLock {
+    _file: File,
+    _guard: MutexGuard<'static, ()>,
+}
+
+/// An attack that ran successfully and has not been cleaned up yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LedgerEntry {
+    pub technique: String,
+    pub test_number: usize,
+    pub guid: String,
+    /// The value of each of the test's arguments, as the attack used it
+    pub vars: BTreeMap<String, String>,
+    /// The arguments the user set, which are quoted. Entries from before this was recorded
+    /// only hold what the user set.
+    #[serde(default)]
+    pub set: Option<BTreeSet<String>>,
+    /// The defaults that use the user's values, as written, so they are quoted the same way
+    #[serde(default)]
+    pub derived: BTreeMap<String, String>,
+    /// The variables were substituted as written rather than quoted
+    #[serde(default)]
+    pub raw_vars: bool,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+}
+
+impl LedgerEntry {
+    pub fn new(
+        technique: &str,
+        test_number: usize,
+        guid: &str,
+        vars: BTreeMap<String, String>,
+    ) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default();
+
+        Self {
+            technique: technique.to_string(),
+            test_number,
+            guid: guid.to_string(),
+            vars,
+            set: None,
+            derived: BTreeMap::new(),
+            raw_vars: false,
+            timestamp,
+        }
+    }
+
+    /// Which of the variables the user set, and the defaults that use them as written.
+    pub fn with_set(mut self, set: BTreeSet<String>, derived: BTreeMap<String, String>) -> Self {
+        self.set = Some(set);
+        self.derived = derived;
+        self
+    }
+
+    /// The values the user set, to give the test again.
+    pub fn set_vars(&self) -> HashMap<String, String> {
+        self.vars
+            .iter()
+            .filter(|(name, _)| self.set.as_ref().is_none_or(|set| set.contains(*name)))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    /// The values of the rest, to use instead of the test's defaults should they have changed.
+    pub fn defaults(&self) -> HashMap<String, String> {
+        let set = self.set_vars();
+
+        self.vars
+            .iter()
+            .filter(|(name, _)| !set.contains_key(*name))
+            .map(|(name, value)| {
+                let value = self.derived.get(name).unwrap_or(value);
+                (name.clone(), value.clone())
+            })
+            .collect()
+    }
+
+    pub fn with_raw_vars(mut self, raw_vars: bool) -> Self {
+        self.raw_vars = raw_vars;
+        self
+    }
+
+    fn same_attack(&self, other: &LedgerEntry) -> bool {
+        self.guid == other.guid && self.vars == other.vars
+    }
+}
+
+/// The on-disk list of attacks that still need their cleanup run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ledger {
+    path: PathBuf,
+}
+
+impl Ledger {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// `$XDG_STATE_HOME/arr/ledger.json`, or the platform's equivalent.
+    pub fn default_path() -> PathBuf {
+        crate::paths::state_dir().join("ledger.json")
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> Result<Vec<LedgerEntry>, ArrError> {
+        // the ledger is replaced in one rename, so reading it needs no lock
+        self.read()
+    }
+
+    pub fn record(&self, entry: LedgerEntry) -> Result<(), ArrError> {
+        let _lock = self.lock()?;
+
+        let mut entries = self.read()?;
+        info!(
+            "Recording {} test {} in the cleanup ledger",
+            &entry.technique, entry.test_number
+        );
+        entries.push(entry);
+
+        self.write(&entries)
+    }
+
+    /// Forget every entry for the same test run with the same variables.
+    pub fn remove(&self, entry: &LedgerEntry) -> Result<(), ArrError> {
+        let _lock = self.lock()?;
+
+        let mut entries = self.read()?;
+        let before = entries.len();
+        entries.retain(|e| !e.same_attack(entry));
+
+        if entries.len() != before {
+            info!(
+                "Removing {} test {} from the cleanup ledger",
+                &entry.technique, entry.test_number
+            );
+            self.write(&entries)?;
+        }
+
+        Ok(())
+    }
+
+    fn err(&self, e: impl std::fmt::Display) -> ArrError {
+        ArrError::LedgerFailure(format!("{}: {}", self.path.display(), e))
+    }
+
+    fn lock(&self) -> Result<LedgerLock, ArrError> {
+        let guard = LEDGER_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| self.err(e))?;
+        }
+        let file = File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(self.path.with_extension("json.lock"))
+            .map_err(|e| self.err(e))?;
+
+        #[cfg(unix)]
+        {
+            use nix::fcntl::{flock, FlockArg};
+            use std::os::unix::io::AsRawFd;
+
+            // released when the file is closed
+            flock(file.as_raw_fd(), FlockArg::LockExclusive).map_err(|e| self.err(e))?;
+        }
+
+        Ok(LedgerLock {
+            _file: file,
+            _guard: guard,
+        })
+    }
+
+    fn read(&self) -> Result<Vec<LedgerEntry>, ArrError> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let contents = fs::read_to_string(&self.path)
+            .map_err(|e| ArrError::LedgerFailure(format!("{}: {}", self.path.display(), e)))?;
+
+        serde_json::from_str(&contents)
+            .map_err(|e| ArrError::LedgerFailure(format!("{}: {}", self.path.display(), e)))
+    }
+
+    fn write(&self, entries: &[LedgerEntry]) -> Result<(), ArrError> {
+        let dir = match self.path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let json = serde_json::to_string_pretty(entries).map_err(|e| self.err(e))?;
+
+        // write to the side and rename, so a crash never leaves half a ledger
+        let mut tmp = tempfile::NamedTempFile::new_in(dir).map_err(|e| self.err(e))?;
+        tmp.write_all(json.as_bytes()).map_err(|e| self.err(e))?;
+        tmp.persist(&self.path).map_err(|e| self.err(e.error))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(guid: &str, value: &str) -> LedgerEntry {
+        let vars = [("output_file".to_string(), value.to_string())].into();
+        LedgerEntry::new("T1574.006", 1, guid, vars)
+    }
+
+    #[test]
+    fn record_and_remove() {
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Ledger::new(dir.path().join("state/ledger.json"));
+
+        assert_eq!(ledger.entries(), Ok(vec![]));
+
+        ledger.record(entry("a", "/tmp/one")).unwrap();
+        ledger.record(entry("a", "/tmp/two")).unwrap();
+        ledger.record(entry("b", "/tmp/one")).unwrap();
+        assert_eq!(ledger.entries().unwrap().len(), 3);
+
+        // only the attack with matching variables is forgotten
+        ledger.remove(&entry("a", "/tmp/one")).unwrap();
+
+        let remaining = ledger.entries().unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0].vars["output_file"], "/tmp/two");
+        assert_eq!(remaining[1].guid, "b");
+    }
+
+    #[test]
+    fn concurrent_ledgers_keep_every_entry() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.json");
+
+        // each thread opens the ledger on its own, like separate arr processes
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = path.clone();
+                scope.spawn(move || {
+                    let ledger = Ledger::new(path);
+                    ledger.record(entry("a", &format!("/tmp/{}", i))).unwrap();
+                });
+            }
+        });
+
+        assert_eq!(Ledger::new(path).entries().unwrap().len(), 8);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn other_processes_are_locked_out() {
+        use nix::fcntl::{flock, FlockArg};
+        use std::os::unix::io::AsRawFd;
+
+        let dir = tempfile::tempdir().unwrap();
+        let ledger = Ledger::new(dir.path().join("ledger.json"));
+
+        let lock = ledger.lock().unwrap();
+        let other = File::open(dir.path().join("ledger.json.lock")).unwrap();
+        assert!(flock(other.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_err());
+
+        drop(lock);
+        assert!(flock(other.as_raw_fd(), FlockArg::LockExclusiveNonblock).is_ok());
+    }
+
+    #[test]
+    fn corrupt_ledger() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger.json");
+        fs::write(&path, "not json").unwrap();
+
+        assert!(matches!(
+            Ledger::new(path).entries(),
+            Err(ArrError::LedgerFailure(_))
+        ));
+    }
+}
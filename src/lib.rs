@@ -1,26 +1,97 @@
+mod argument;
+mod builtin;
 mod error;
+mod executor;
 mod find_file;
+mod index;
+mod info;
+mod install;
+mod ledger;
+mod lint;
+mod list;
+mod manual;
 mod parse_command;
 mod parse_yaml;
+mod paths;
+mod plan;
+mod process;
+mod quote;
+mod report;
+mod roots;
+mod scheduler;
+mod selector;
+#[cfg(test)]
+mod test_support;
 mod util;
+mod values;
 
-pub use util::{get_all_executors, parse_all};
+pub use argument::ArgType;
+pub use error::{ArrError, SourceError, Unresolved};
+pub use executor::{executor_for, executor_names, Delivery, Executor};
+pub use info::{ArgumentInfo, CommandInfo, DependencyInfo, TechniqueInfo, TestInfo};
+pub use install::{get, rollback, update, InstallReport, Lockfile, Source, SourceKind};
+pub use ledger::{Ledger, LedgerEntry};
+pub use lint::{lint, LintIssue, PLATFORMS};
+pub use list::{list_tests, TestFilter, TestSummary};
+pub use manual::ManualOutcome;
+pub use paths::index_dir;
+pub use plan::{ExecutionPlan, PlannedCommand, Prerequisite};
+pub use process::{interrupted, watch_interrupts};
+pub use report::{ExecutionFailure, ExecutionReport, Phase, PhaseReport};
+pub use scheduler::{run_jobs, Constraints, Job};
+pub use selector::TestSelector;
+pub use util::{
+    cleanup_pending, get_all_executors, parse_all, run_all, PendingCleanup, RunOptions,
+    TestOutcome, TestResult,
+};
+pub use values::{ValueSource, VarSources, ENV_PREFIX};
 
-use error::ArrError;
-use find_file::{find_atomics_dir, find_file};
-use parse_command::{parse_command, update_path};
-use parse_yaml::{parse_art_file, AtomicReadTeamTechnique};
+use argument::closest;
+use builtin::Builtins;
+use manual::perform_steps;
+use parse_command::{fill, parse_command, placeholders, variables, Variables};
+use parse_yaml::{AtomicReadTeamTechnique, AtomicTest};
+use process::{check_interrupted, output_with_timeout, with_default_interrupts, write_script};
+use roots::Roots;
 
 use log::{error, info};
 use std::collections::HashMap;
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long each phase of a test may run before it is killed. `None` waits forever.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Timeouts {
+    pub check: Option<Duration>,
+    pub dependency: Option<Duration>,
+    pub attack: Option<Duration>,
+    pub cleanup: Option<Duration>,
+}
+
+/// Whether one of a test's dependencies is in place.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrereqStatus {
+    pub description: String,
+    pub met: bool,
+}
 
 pub struct Arr {
     technique: String,
     vars: HashMap<String, String>,
     test_num: usize,
-    art_path: PathBuf,
+    /// Atomics roots, highest priority first
+    art_paths: Vec<PathBuf>,
+    /// Where the technique indexes are cached, if anywhere
+    index_dir: Option<PathBuf>,
+    timeouts: Timeouts,
+    ledger: Option<Ledger>,
+    cleanup_on_failure: bool,
+    raw_vars: bool,
+    var_sources: VarSources,
+    /// Used instead of the test's defaults, as recorded in the ledger
+    defaults: HashMap<String, String>,
 }
 
 impl Arr {
@@ -28,131 +99,473 @@ impl Arr {
         technique: String,
         vars: HashMap<String, String>,
         test_num: usize,
-        art_path: PathBuf,
+        art_paths: Vec<PathBuf>,
     ) -> Self {
         Self {
             technique,
             vars,
             test_num,
-            art_path,
+            art_paths,
+            index_dir: None,
+            timeouts: Timeouts::default(),
+            ledger: None,
+            cleanup_on_failure: false,
+            raw_vars: false,
+            var_sources: VarSources::default(),
+            defaults: HashMap::new(),
         }
     }
 
-    pub fn run(&self) -> Result<(), ArrError> {
-        // find the `atomics` directory
-        let atomics_dir = find_atomics_dir(&self.art_path)?;
+    /// One `Arr` for each test in the technique that the selector picks.
+    pub fn select(
+        technique: &str,
+        selector: &TestSelector,
+        vars: HashMap<String, String>,
+        art_paths: Vec<PathBuf>,
+        index_dir: Option<PathBuf>,
+    ) -> Result<Vec<Self>, ArrError> {
+        let yaml = Roots::open(&art_paths, index_dir.as_deref())?.technique(technique)?;
+        let test_nums = selector.resolve(&yaml)?;
 
-        // find the YAML file
-        let art_file = find_file(&self.technique, &atomics_dir)?;
+        let selected: Vec<&AtomicTest> = test_nums.iter().map(|&i| &yaml.atomic_tests[i]).collect();
+        check_var_names(&selected, &vars)?;
 
-        // parse the YAML
-        let yaml = parse_art_file(&art_file)?;
+        let tests = test_nums
+            .into_iter()
+            .zip(selected)
+            .map(|(test_num, test)| {
+                Self::new(
+                    technique.to_string(),
+                    vars_for(test, &vars),
+                    test_num,
+                    art_paths.clone(),
+                )
+                .with_index_dir(index_dir.clone())
+            })
+            .collect();
 
-        // verify the chosen test works with this OS
-        is_os_supported(&yaml, self.test_num)?;
+        Ok(tests)
+    }
 
-        // check super user privileges
-        if cfg!(unix) {
-            check_superuser_requirement(&yaml, self.test_num)?;
+    /// The zero-based position of the test in its technique.
+    pub fn test_num(&self) -> usize {
+        self.test_num
+    }
+
+    pub fn technique(&self) -> &str {
+        &self.technique
+    }
+
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Cache the technique indexes in this directory, or index the roots each time without one.
+    pub fn with_index_dir(mut self, index_dir: Option<PathBuf>) -> Self {
+        self.index_dir = index_dir;
+        self
+    }
+
+    /// Record successful attacks in the ledger, and forget them once cleaned up.
+    pub fn with_ledger(mut self, ledger: Ledger) -> Self {
+        self.ledger = Some(ledger);
+        self
+    }
+
+    /// Substitute the variables as given, rather than quoted for the executor's shell.
+    pub fn with_raw_vars(mut self, raw_vars: bool) -> Self {
+        self.raw_vars = raw_vars;
+        self
+    }
+
+    /// Use the values from a vars file and the environment for any arguments the test takes.
+    pub fn with_var_sources(mut self, var_sources: VarSources) -> Self {
+        self.var_sources = var_sources;
+        self
+    }
+
+    /// Use these defaults instead of the test's, so a cleanup runs with what its attack used.
+    pub(crate) fn with_defaults(mut self, defaults: HashMap<String, String>) -> Self {
+        self.defaults = defaults;
+        self
+    }
+
+    /// Run the cleanup when the attack fails, times out or is interrupted.
+    pub fn with_cleanup_on_failure(mut self, cleanup_on_failure: bool) -> Self {
+        self.cleanup_on_failure = cleanup_on_failure;
+        self
+    }
+
+    /// Get any missing prerequisites and run the attack.
+    ///
+    /// Whether or not it succeeds, the report has every command that ran.
+    pub fn run(&self) -> Result<ExecutionReport, ExecutionFailure> {
+        self.reporting(|report| self.run_attack(report))
+    }
+
+    fn run_attack(&self, report: &mut ExecutionReport) -> Result<(), ArrError> {
+        let yaml = self.load()?;
+        let (test, args) = self.prepare(&yaml)?;
+        report.technique = yaml.attack_technique.clone();
+
+        // check the dependencies, and get any that are missing
+        let prerequisites = get_prerequisites(test, &args)?;
+        for status in self.satisfy_prerequisites(&prerequisites, true, report)? {
+            if !status.met {
+                return Err(ArrError::PrerequisiteNotMet(status.description));
+            }
         }
 
-        // combine default and provided variables
-        let args = gather_args(&yaml, self.vars.clone(), self.test_num, &atomics_dir);
+        // run the attack, unless interrupted while getting ready
+        let (attack_command, attack_executor) = get_attack_command(test, &args)?;
+        check_interrupted()?;
+
+        let res = execute(
+            &attack_command,
+            &attack_executor,
+            Phase::Attack,
+            self.timeouts.attack,
+            report,
+        );
 
-        // run the check
-        let check_command = get_check_command(&yaml, self.test_num, &atomics_dir, &args)?;
-        for (command, executor) in check_command {
-            execute(&command, &executor)?;
+        match res {
+            Ok(_) => {
+                if let Some(ledger) = &self.ledger {
+                    if let Err(e) = ledger.record(self.ledger_entry(&yaml, test, &args)) {
+                        error!("{}", e);
+                    }
+                }
+                Ok(())
+            }
+            Err(ArrError::ManualStepsSkipped) => Err(ArrError::ManualStepsSkipped),
+            Err(e) => {
+                if self.cleanup_on_failure {
+                    info!("The attack did not finish, running its cleanup");
+                    if let Err(e) = self.execute_cleanup(&yaml, test, &args, report) {
+                        error!("Cleanup failed: {}", e);
+                    }
+                }
+                Err(e)
+            }
         }
+    }
+
+    /// Run each dependency's check without changing anything.
+    pub fn check_prereqs(&self) -> Result<Vec<PrereqStatus>, ArrError> {
+        let yaml = self.load()?;
+        let (test, args) = self.prepare(&yaml)?;
+
+        let mut report = ExecutionReport::new(&yaml.attack_technique, self.test_num + 1);
+
+        let prerequisites = get_prerequisites(test, &args)?;
+        self.satisfy_prerequisites(&prerequisites, false, &mut report)
+    }
+
+    /// Get every dependency whose check fails, then check it again.
+    pub fn get_prereqs(&self) -> Result<Vec<PrereqStatus>, ArrError> {
+        let yaml = self.load()?;
+        let (test, args) = self.prepare(&yaml)?;
 
-        // run the dependency
-        let dependency_command = get_dependency_command(&yaml, self.test_num, &atomics_dir, &args)?;
-        for (command, executor) in dependency_command {
-            execute(&command, &executor)?;
+        let mut report = ExecutionReport::new(&yaml.attack_technique, self.test_num + 1);
+
+        let prerequisites = get_prerequisites(test, &args)?;
+        self.satisfy_prerequisites(&prerequisites, true, &mut report)
+    }
+
+    /// Run the cleanup. Whether or not it succeeds, the report has the command that ran.
+    pub fn cleanup(&self) -> Result<ExecutionReport, ExecutionFailure> {
+        self.reporting(|report| {
+            let yaml = self.load()?;
+            let (test, args) = self.prepare(&yaml)?;
+            report.technique = yaml.attack_technique.clone();
+
+            self.execute_cleanup(&yaml, test, &args, report)
+        })
+    }
+
+    /// Keep the report of whatever ran, however `f` ends.
+    fn reporting(
+        &self,
+        f: impl FnOnce(&mut ExecutionReport) -> Result<(), ArrError>,
+    ) -> Result<ExecutionReport, ExecutionFailure> {
+        let mut report = ExecutionReport::new(&self.technique, self.test_num + 1);
+
+        match f(&mut report) {
+            Ok(()) => Ok(report),
+            Err(source) => Err(ExecutionFailure {
+                report: Box::new(report),
+                source,
+            }),
         }
+    }
 
-        // run the attack
-        let (attack_command, attack_executor) =
-            get_attack_command(&yaml, self.test_num, &atomics_dir, &args)?;
+    /// Work out exactly what `run` and `cleanup` would execute, without executing anything.
+    pub fn plan(&self) -> Result<ExecutionPlan, ArrError> {
+        let yaml = self.load()?;
+        // nothing runs, so the plan doesn't need root even if the test does
+        let (test, args) = self.resolve(&yaml)?;
 
-        execute(&attack_command, &attack_executor)?;
+        let prerequisites = get_prerequisites(test, &args)?;
 
-        Ok(())
+        let (command, executor) = get_attack_command(test, &args)?;
+        let attack = PlannedCommand {
+            phase: Phase::Attack,
+            program: program_line(&executor)?,
+            executor,
+            command,
+        };
+
+        let (command, executor) = get_cleanup_command(test, &args)?;
+        let cleanup = match command.is_empty() {
+            true => None,
+            false => Some(PlannedCommand {
+                phase: Phase::Cleanup,
+                program: program_line(&executor)?,
+                executor,
+                command,
+            }),
+        };
+
+        Ok(ExecutionPlan {
+            technique: yaml.attack_technique.clone(),
+            test_number: self.test_num + 1,
+            test_name: test.name.clone(),
+            guid: test.auto_generated_guid.clone(),
+            elevation_required: test.executor.elevation_required.unwrap_or(false),
+            variables: args.values.into_iter().collect(),
+            prerequisites,
+            attack,
+            cleanup,
+        })
     }
 
-    pub fn cleanup(&self) -> Result<(), ArrError> {
-        // find the `atomics` directory
-        let atomics_dir = find_atomics_dir(&self.art_path)?;
+    /// Load the technique's YAML, merged across every root.
+    fn load(&self) -> Result<AtomicReadTeamTechnique, ArrError> {
+        let mut yaml =
+            Roots::open(&self.art_paths, self.index_dir.as_deref())?.technique(&self.technique)?;
 
-        // find the YAML file
-        let art_file = find_file(&self.technique, &atomics_dir)?;
+        if let Some(test) = yaml.atomic_tests.get_mut(self.test_num) {
+            for (name, default) in &self.defaults {
+                if let Some(arg) = test.input_arguments.get_mut(name) {
+                    arg.default = default.clone();
+                }
+            }
+        }
 
-        // parse the YAML
-        let yaml = parse_art_file(&art_file)?;
+        Ok(yaml)
+    }
 
-        // verify the chosen test works with this OS
-        is_os_supported(&yaml, self.test_num)?;
+    /// Find the test and work out its variables, making sure it can run here.
+    fn prepare<'a>(
+        &self,
+        yaml: &'a AtomicReadTeamTechnique,
+    ) -> Result<(&'a AtomicTest, Variables), ArrError> {
+        let (test, args) = self.resolve(yaml)?;
 
         // check super user privileges
         if cfg!(unix) {
-            check_superuser_requirement(&yaml, self.test_num)?;
+            check_superuser_requirement(&yaml.attack_technique, test)?;
         }
 
+        Ok((test, args))
+    }
+
+    /// Find the test and work out its variables, without checking the user's privileges.
+    fn resolve<'a>(
+        &self,
+        yaml: &'a AtomicReadTeamTechnique,
+    ) -> Result<(&'a AtomicTest, Variables), ArrError> {
+        let test = yaml.test(self.test_num)?;
+
+        // verify the chosen test works with this OS
+        is_os_supported(&yaml.attack_technique, test)?;
+
         // combine default and provided variables
-        let args = gather_args(&yaml, self.vars.clone(), self.test_num, &atomics_dir);
+        let args = gather_args(
+            &yaml.attack_technique,
+            test,
+            self.vars.clone(),
+            &self.var_sources,
+            self.raw_vars,
+        )?;
+        check_placeholders(test, &args)?;
 
-        // run the cleanup
-        let (cleanup_command, cleanup_executor) =
-            get_cleanup_command(&yaml, self.test_num, &atomics_dir, &args)?;
+        Ok((test, args))
+    }
+
+    fn execute_cleanup(
+        &self,
+        yaml: &AtomicReadTeamTechnique,
+        test: &AtomicTest,
+        args: &Variables,
+        report: &mut ExecutionReport,
+    ) -> Result<(), ArrError> {
+        let (cleanup_command, cleanup_executor) = get_cleanup_command(test, args)?;
 
         if cleanup_command.is_empty() {
             error!("This test does not have a cleanup command");
             return Err(ArrError::Other("No cleanup command".to_string()));
         }
 
-        execute(&cleanup_command, &cleanup_executor)?;
+        execute(
+            &cleanup_command,
+            &cleanup_executor,
+            Phase::Cleanup,
+            self.timeouts.cleanup,
+            report,
+        )?;
+
+        if let Some(ledger) = &self.ledger {
+            ledger.remove(&self.ledger_entry(yaml, test, args))?;
+        }
 
         Ok(())
     }
+
+    fn ledger_entry(
+        &self,
+        yaml: &AtomicReadTeamTechnique,
+        test: &AtomicTest,
+        args: &Variables,
+    ) -> LedgerEntry {
+        // the built-ins are worked out again from wherever the atomics are
+        let vars = args
+            .values
+            .iter()
+            .filter(|(name, _)| test.input_arguments.contains_key(*name))
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+
+        LedgerEntry::new(
+            &yaml.attack_technique,
+            self.test_num + 1,
+            &test.auto_generated_guid,
+            vars,
+        )
+        .with_set(
+            args.set.iter().cloned().collect(),
+            args.derived
+                .iter()
+                .map(|(name, default)| (name.clone(), default.clone()))
+                .collect(),
+        )
+        .with_raw_vars(self.raw_vars)
+    }
+
+    fn satisfy_prerequisites(
+        &self,
+        prerequisites: &[Prerequisite],
+        get_missing: bool,
+        report: &mut ExecutionReport,
+    ) -> Result<Vec<PrereqStatus>, ArrError> {
+        let mut statuses = Vec::new();
+
+        for prerequisite in prerequisites {
+            check_interrupted()?;
+            let mut met = check(
+                &prerequisite.check_command,
+                &prerequisite.executor,
+                self.timeouts.check,
+                report,
+            )?;
+
+            if !met && get_missing {
+                info!("Prerequisite not met: {}", &prerequisite.description);
+                check_interrupted()?;
+
+                if let Err(e) = execute(
+                    &prerequisite.get_command,
+                    &prerequisite.executor,
+                    Phase::Dependency,
+                    self.timeouts.dependency,
+                    report,
+                ) {
+                    error!("Failed to get the prerequisite: {}", e);
+                }
+
+                met = check(
+                    &prerequisite.check_command,
+                    &prerequisite.executor,
+                    self.timeouts.check,
+                    report,
+                )?;
+            }
+
+            match met {
+                true => info!("Prerequisite met: {}", &prerequisite.description),
+                false if get_missing => {
+                    error!("Unable to meet prerequisite: {}", &prerequisite.description)
+                }
+                false => info!("Prerequisite not met: {}", &prerequisite.description),
+            }
+
+            statuses.push(PrereqStatus {
+                description: prerequisite.description.clone(),
+                met,
+            });
+        }
+
+        Ok(statuses)
+    }
 }
 
-fn is_os_supported(yaml: &AtomicReadTeamTechnique, test_num: usize) -> Result<(), ArrError> {
-    let local_os = std::env::consts::OS;
+/// Describe a technique's tests without running anything, whether or not they support this OS.
+///
+/// Without a selector every test is described.
+pub fn technique_info(
+    technique: &str,
+    selector: Option<&TestSelector>,
+    vars: HashMap<String, String>,
+    var_sources: &VarSources,
+    raw_vars: bool,
+    art_paths: &[PathBuf],
+    index_dir: Option<&Path>,
+) -> Result<TechniqueInfo, ArrError> {
+    let yaml = Roots::open(art_paths, index_dir)?.technique(technique)?;
 
-    let res = yaml.atomic_tests[test_num]
-        .supported_platforms
-        .iter()
-        .any(|os| os.to_lowercase().trim().eq(local_os));
+    let test_nums = match selector {
+        Some(selector) => selector.resolve(&yaml)?,
+        None => (0..yaml.atomic_tests.len()).collect(),
+    };
+
+    let shown: Vec<&AtomicTest> = test_nums.iter().map(|&i| &yaml.atomic_tests[i]).collect();
+    check_var_names(&shown, &vars)?;
 
-    match res {
+    TechniqueInfo::new(&yaml, &test_nums, &vars, var_sources, raw_vars)
+}
+
+fn is_os_supported(technique: &str, test: &AtomicTest) -> Result<(), ArrError> {
+    let local_os = std::env::consts::OS;
+
+    match supports_os(test) {
         true => {
-            info!(
-                "Technique: {}, supports {}",
-                &yaml.attack_technique, &local_os
-            );
+            info!("Technique: {}, supports {}", technique, &local_os);
             Ok(())
         }
         false => {
             error!(
                 "{} not supported for Technique: {}, Test: {}.",
-                local_os, &yaml.attack_technique, test_num
+                local_os, technique, &test.name
             );
             Err(ArrError::OsNotSupported)
         }
     }
 }
 
+fn supports_os(test: &AtomicTest) -> bool {
+    let local_os = std::env::consts::OS;
+
+    test.supported_platforms
+        .iter()
+        .any(|os| os.to_lowercase().trim().eq(local_os))
+}
+
 #[cfg(target_family = "unix")]
-fn check_superuser_requirement(
-    yaml: &AtomicReadTeamTechnique,
-    test_num: usize,
-) -> Result<(), ArrError> {
-    if let Some(er) = &yaml.atomic_tests[test_num].executor.elevation_required {
+fn check_superuser_requirement(technique: &str, test: &AtomicTest) -> Result<(), ArrError> {
+    if let Some(er) = &test.executor.elevation_required {
         if *er && !nix::unistd::getuid().is_root() {
-            error!(
-                "Technique {} test {} requires root.",
-                &yaml.attack_technique, test_num
-            );
+            error!("Technique {} test {} requires root.", technique, &test.name);
             return Err(ArrError::RootRequired);
         }
     }
@@ -160,49 +573,246 @@ fn check_superuser_requirement(
     Ok(())
 }
 
+/// Work out the value of each of the test's variables.
+///
+/// The user's values come from the vars file, then `ARR_VAR_<name>` environment variables,
+/// then `--set-var`, each taking precedence over the one before, and all of them over the
+/// test's defaults. Defaults can use the other variables, and are worked out after them.
 fn gather_args(
-    yaml: &AtomicReadTeamTechnique,
+    technique: &str,
+    test: &AtomicTest,
     vars: HashMap<String, String>,
-    test_num: usize,
-    atomics_dir: &Path,
-) -> HashMap<String, String> {
-    // get the default args
-    let mut args: HashMap<String, String> = yaml.atomic_tests[test_num]
-        .input_arguments
-        .iter()
-        .map(|(k, v)| (k.clone(), v.default.clone()))
-        .collect();
+    var_sources: &VarSources,
+    raw: bool,
+) -> Result<Variables, ArrError> {
+    let mut given: HashMap<String, (String, ValueSource)> = HashMap::new();
+    for (source, values) in var_sources.layers(technique, &test.auto_generated_guid) {
+        for (name, value) in vars_for(test, &values) {
+            given.insert(name, (value, source));
+        }
+    }
+    for (name, value) in vars {
+        if !test.input_arguments.contains_key(&name) {
+            return Err(unknown_argument(&name, &[test]));
+        }
+        given.insert(name, (value, ValueSource::SetVar));
+    }
+
+    let builtins = Builtins::for_test(test);
+    let mut values = HashMap::new();
+    let mut sources = HashMap::new();
+    let mut derived = HashMap::new();
+
+    // the user's values are checked against the argument's type
+    for (name, (value, source)) in given {
+        let arg_type = &test.input_arguments[&name].arg_type;
+
+        let value = match arg_type.coerce(&value) {
+            Some(value) => value,
+            None => {
+                return Err(ArrError::InvalidArgValue {
+                    name,
+                    value,
+                    expected: arg_type.clone(),
+                })
+            }
+        };
+
+        values.insert(name.clone(), builtins.substitute(&value));
+        sources.insert(name, source);
+    }
+
+    // the built-ins are `#{}` variables too, unless an argument is named the same
+    for (name, value) in builtins.iter() {
+        if !test.input_arguments.contains_key(name) {
+            values.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    let mut names: Vec<&String> = test.input_arguments.keys().collect();
+    names.sort();
+    for name in names {
+        resolve_default(
+            test,
+            name,
+            &builtins,
+            &mut values,
+            &mut sources,
+            &mut derived,
+            &mut vec![],
+        )?;
+    }
+
+    for (name, source) in &sources {
+        info!("Set variable `{}` to `{}` ({})", name, values[name], source);
+    }
+
+    Ok(Variables {
+        values,
+        set: sources
+            .iter()
+            .filter(|(_, source)| !matches!(source, ValueSource::Default | ValueSource::Derived))
+            .map(|(name, _)| name.clone())
+            .collect(),
+        sources,
+        derived,
+        raw,
+        builtins,
+    })
+}
+
+/// Work out an argument's default, after any of the test's arguments it uses.
+///
+/// A default that uses the user's values is also kept as written, with those values still to
+/// substitute, so that only they are quoted and the rest is left to the shell like any default.
+fn resolve_default(
+    test: &AtomicTest,
+    name: &str,
+    builtins: &Builtins,
+    values: &mut HashMap<String, String>,
+    sources: &mut HashMap<String, ValueSource>,
+    derived: &mut HashMap<String, String>,
+    resolving: &mut Vec<String>,
+) -> Result<(), ArrError> {
+    if sources.contains_key(name) {
+        return Ok(());
+    }
 
-    // replace defaults with user's args
-    args.extend(vars);
+    if let Some(i) = resolving.iter().position(|n| n == name) {
+        let mut cycle = resolving[i..].to_vec();
+        cycle.push(name.to_string());
+        return Err(ArrError::ArgumentCycle(cycle));
+    }
+
+    let default = builtins.substitute(&test.input_arguments[name].default);
 
-    // parse the args to update the "PathToAtomics"
-    let mut parsed_args = HashMap::new();
+    resolving.push(name.to_string());
+    let mut source = ValueSource::Default;
+    for used in variables(&default) {
+        if test.input_arguments.contains_key(used) {
+            resolve_default(test, used, builtins, values, sources, derived, resolving)?;
+            if sources[used] != ValueSource::Default {
+                source = ValueSource::Derived;
+            }
+        }
+    }
+    resolving.pop();
 
-    for (k, v) in args.into_iter() {
-        let (_, v) = update_path(&atomics_dir.to_string_lossy())(&v).unwrap();
+    if source == ValueSource::Derived {
+        derived.insert(name.to_string(), fill(&default, derived));
+    }
 
-        info!("Set variable `{}` to `{}`", &k, &v);
+    values.insert(name.to_string(), fill(&default, values));
+    sources.insert(name.to_string(), source);
 
-        parsed_args.insert(k, v);
+    Ok(())
+}
+
+/// Reject any variable that none of the tests take.
+fn check_var_names(tests: &[&AtomicTest], vars: &HashMap<String, String>) -> Result<(), ArrError> {
+    match vars
+        .keys()
+        .find(|name| !tests.iter().any(|t| t.input_arguments.contains_key(*name)))
+    {
+        Some(name) => Err(unknown_argument(name, tests)),
+        None => Ok(()),
     }
+}
 
-    parsed_args
+/// The variables the test takes, leaving out those meant for other tests.
+fn vars_for(test: &AtomicTest, vars: &HashMap<String, String>) -> HashMap<String, String> {
+    vars.iter()
+        .filter(|(name, _)| test.input_arguments.contains_key(*name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect()
+}
+
+fn unknown_argument(name: &str, tests: &[&AtomicTest]) -> ArrError {
+    unknown_name(
+        name,
+        tests
+            .iter()
+            .flat_map(|t| t.input_arguments.keys().cloned())
+            .collect(),
+    )
+}
+
+/// A variable that is none of these arguments, with the one it is most likely a misspelling of.
+fn unknown_name(name: &str, mut available: Vec<String>) -> ArrError {
+    available.sort();
+    available.dedup();
+
+    error!("`{}` is not an input argument", name);
+
+    ArrError::UnknownArgument {
+        name: name.to_string(),
+        closest: closest(name, available.iter().map(String::as_str)).map(String::from),
+        available,
+    }
+}
+
+/// Report every variable without a value across the test's commands, rather than just the first.
+fn check_placeholders(test: &AtomicTest, vars: &Variables) -> Result<(), ArrError> {
+    let mut commands: Vec<(Phase, Option<usize>, &str)> = Vec::new();
+    for (i, dependency) in test.dependencies.iter().flatten().enumerate() {
+        commands.push((Phase::Check, Some(i + 1), &dependency.prereq_command));
+        commands.push((
+            Phase::Dependency,
+            Some(i + 1),
+            &dependency.get_prereq_command,
+        ));
+    }
+    commands.push((Phase::Attack, None, attack_script(test)?));
+    commands.push((
+        Phase::Cleanup,
+        None,
+        test.executor.cleanup_command.as_deref().unwrap_or_default(),
+    ));
+
+    let mut available: Vec<String> = test.input_arguments.keys().cloned().collect();
+    available.sort();
+
+    let mut unresolved = Vec::new();
+    for (phase, dependency, command) in commands {
+        for (line, column, name) in placeholders(command) {
+            if !vars.values.contains_key(name) {
+                unresolved.push(Unresolved {
+                    name: name.to_string(),
+                    phase,
+                    dependency,
+                    line,
+                    column,
+                    closest: closest(name, available.iter().map(String::as_str)).map(String::from),
+                });
+            }
+        }
+    }
+
+    match unresolved.is_empty() {
+        true => Ok(()),
+        false => {
+            for u in &unresolved {
+                error!("`{}` has no value", u.name);
+            }
+            Err(ArrError::UnresolvedVariables {
+                unresolved,
+                available,
+            })
+        }
+    }
 }
 
 fn get_check_command(
-    yaml: &AtomicReadTeamTechnique,
-    test_num: usize,
-    atomics_dir: &Path,
-    vars: &HashMap<String, String>,
+    test: &AtomicTest,
+    vars: &Variables,
 ) -> Result<Vec<(String, String)>, ArrError> {
     let mut commands: Vec<(String, String)> = Vec::new();
-    if let Some(dependency_executor) = &yaml.atomic_tests[test_num].dependency_executor_name {
-        if let Some(dependencies) = &yaml.atomic_tests[test_num].dependencies {
-            for dependency in dependencies {
-                let command = parse_commands(&dependency.prereq_command, atomics_dir, vars)?;
-                commands.push((command, dependency_executor.to_string()));
-            }
+    let dependency_executor = dependency_executor(test);
+    if let Some(dependencies) = &test.dependencies {
+        for dependency in dependencies {
+            let command =
+                parse_commands(&dependency.prereq_command, vars, Some(dependency_executor))?;
+            commands.push((command, dependency_executor.to_string()));
         }
     }
 
@@ -215,18 +825,19 @@ fn get_check_command(
 }
 
 fn get_dependency_command(
-    yaml: &AtomicReadTeamTechnique,
-    test_num: usize,
-    atomics_dir: &Path,
-    vars: &HashMap<String, String>,
+    test: &AtomicTest,
+    vars: &Variables,
 ) -> Result<Vec<(String, String)>, ArrError> {
     let mut commands: Vec<(String, String)> = Vec::new();
-    if let Some(dependency_executor) = &yaml.atomic_tests[test_num].dependency_executor_name {
-        if let Some(dependencies) = &yaml.atomic_tests[test_num].dependencies {
-            for dependency in dependencies {
-                let command = parse_commands(&dependency.get_prereq_command, atomics_dir, vars)?;
-                commands.push((command, dependency_executor.to_string()));
-            }
+    let dependency_executor = dependency_executor(test);
+    if let Some(dependencies) = &test.dependencies {
+        for dependency in dependencies {
+            let command = parse_commands(
+                &dependency.get_prereq_command,
+                vars,
+                Some(dependency_executor),
+            )?;
+            commands.push((command, dependency_executor.to_string()));
         }
     }
 
@@ -238,19 +849,60 @@ fn get_dependency_command(
     Ok(commands)
 }
 
-fn get_attack_command(
-    yaml: &AtomicReadTeamTechnique,
-    test_num: usize,
-    atomics_dir: &Path,
-    vars: &HashMap<String, String>,
-) -> Result<(String, String), ArrError> {
-    let command = yaml.atomic_tests[test_num]
-        .executor
-        .command
-        .clone()
-        .unwrap_or_else(|| "".to_string());
-    let executor = yaml.atomic_tests[test_num].executor.name.to_string();
-    let command = parse_commands(&command, atomics_dir, vars)?;
+/// Pair each dependency's check with the command that gets it.
+fn get_prerequisites(test: &AtomicTest, vars: &Variables) -> Result<Vec<Prerequisite>, ArrError> {
+    let check_commands = get_check_command(test, vars)?;
+    let dependency_commands = get_dependency_command(test, vars)?;
+    let dependencies = test.dependencies.iter().flatten();
+
+    let prerequisites = dependencies
+        .zip(check_commands)
+        .zip(dependency_commands)
+        .map(
+            |((dependency, (check_command, executor)), (get_command, _))| {
+                Ok(Prerequisite {
+                    description: parse_commands(dependency.description.trim(), vars, None)
+                        .unwrap_or_else(|_| dependency.description.trim().to_string()),
+                    program: program_line(&executor)?,
+                    executor,
+                    check_command,
+                    get_command,
+                })
+            },
+        )
+        .collect::<Result<_, ArrError>>()?;
+
+    Ok(prerequisites)
+}
+
+/// The command line an executor is run with, showing the script as `<script>`.
+fn program_line(executor: &str) -> Result<Option<String>, ArrError> {
+    let executor = executor_for(executor)?;
+    let script = match executor.delivery() {
+        Delivery::Argument => "<script>".to_string(),
+        Delivery::ScriptFile(extension) => format!("<script>.{}", extension),
+        Delivery::Manual => return Ok(None),
+    };
+
+    Ok(executor.command(OsStr::new(&script)).map(|command| {
+        std::iter::once(command.get_program())
+            .chain(command.get_args())
+            .map(|arg| arg.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }))
+}
+
+/// Dependencies run with the test's own executor unless they name one.
+fn dependency_executor(test: &AtomicTest) -> &str {
+    test.dependency_executor_name
+        .as_deref()
+        .unwrap_or(&test.executor.name)
+}
+
+fn get_attack_command(test: &AtomicTest, vars: &Variables) -> Result<(String, String), ArrError> {
+    let executor = test.executor.name.to_string();
+    let command = parse_commands(attack_script(test)?, vars, Some(&executor))?;
 
     info!("The attack executor is `{}`", &executor);
     info!("The attack command is `{}`", &command);
@@ -258,19 +910,23 @@ fn get_attack_command(
     Ok((command, executor))
 }
 
-fn get_cleanup_command(
-    yaml: &AtomicReadTeamTechnique,
-    test_num: usize,
-    atomics_dir: &Path,
-    vars: &HashMap<String, String>,
-) -> Result<(String, String), ArrError> {
-    let command = yaml.atomic_tests[test_num]
-        .executor
-        .cleanup_command
-        .clone()
-        .unwrap_or("".to_string());
-    let executor = yaml.atomic_tests[test_num].executor.name.to_string();
-    let command = parse_commands(&command, atomics_dir, vars)?;
+/// The attack's command, or its steps for manual tests.
+fn attack_script(test: &AtomicTest) -> Result<&str, ArrError> {
+    let test_executor = &test.executor;
+
+    // manual tests describe their attack as steps for the operator
+    let command = match executor_for(&test_executor.name)?.delivery() {
+        Delivery::Manual => &test_executor.steps,
+        _ => &test_executor.command,
+    };
+
+    Ok(command.as_deref().unwrap_or_default())
+}
+
+fn get_cleanup_command(test: &AtomicTest, vars: &Variables) -> Result<(String, String), ArrError> {
+    let command = test.executor.cleanup_command.clone().unwrap_or_default();
+    let executor = test.executor.name.to_string();
+    let command = parse_commands(&command, vars, Some(&executor))?;
 
     info!("The cleanup executor is `{}`", &executor);
     info!("The cleanup command is `{}`", &command);
@@ -278,57 +934,1183 @@ fn get_cleanup_command(
     Ok((command, executor))
 }
 
+/// Substitute the variables in a script.
+///
+/// The script is kept exactly as written, so heredocs, line continuations and
+/// blocks spanning several lines reach the executor intact. The values the user
+/// set are quoted for the executor's shell, text that is only read has no executor.
 fn parse_commands(
     commands: &str,
-    atomics_dir: &Path,
-    vars: &HashMap<String, String>,
+    vars: &Variables,
+    executor: Option<&str>,
 ) -> Result<String, ArrError> {
-    let parsed_commands = commands
-        .lines()
-        .map(|command| parse_command(command, atomics_dir, vars))
-        .collect::<Result<Vec<_>, _>>()?;
+    let quoting = executor
+        .and_then(|executor| executor_for(executor).ok())
+        .and_then(|executor| executor.quoting());
 
-    let commands = parsed_commands.join(";");
+    parse_command(commands, vars, quoting)
+}
 
-    Ok(commands)
+fn program(executor: &dyn Executor, script: &OsStr) -> Result<Command, ArrError> {
+    executor
+        .command(script)
+        .ok_or_else(|| ArrError::Other(format!("`{}` is not run as a command", executor.name())))
 }
 
-fn execute(command: &str, executor: &str) -> Result<(), ArrError> {
+fn run_command(
+    command: &str,
+    executor: &str,
+    phase: Phase,
+    timeout: Option<Duration>,
+) -> Result<PhaseReport, ArrError> {
     log::info!("Using `{}` to execute the command: {}", &executor, &command);
 
-    let executor_arg = if executor.eq("cmd") { "/c" } else { "-c" };
+    let executor = executor_for(executor)?;
+    let script = executor.render(command);
 
-    let output = Command::new(executor)
-        .arg(executor_arg)
-        .arg(&command)
-        .output()
-        .map_err(|e| ArrError::CommandIoFailure(e.to_string()))?;
+    let started = SystemTime::now();
+    let start = Instant::now();
 
-    match output.status.success() {
-        true => {
-            info!("Command executed with a successful return code");
-            Ok(())
+    let output = match executor.delivery() {
+        Delivery::Argument => {
+            output_with_timeout(&mut program(executor, script.as_ref())?, timeout)?
         }
-        false => {
-            let mut stdout = String::from_utf8_lossy(&output.stdout);
-            let mut stderr = String::from_utf8_lossy(&output.stderr);
+        Delivery::ScriptFile(extension) => {
+            let path = write_script(&script, extension)?;
 
-            stdout = if stdout.len() > 0 {
-                stdout
-            } else {
-                std::borrow::Cow::Borrowed("(None)")
-            };
+            output_with_timeout(&mut program(executor, path.as_os_str())?, timeout)?
+        }
+        Delivery::Manual => {
+            let outcome = with_default_interrupts(|| perform_steps(&script))?;
+
+            return Ok(PhaseReport {
+                phase,
+                command: command.to_string(),
+                executor: executor.name().to_string(),
+                exit_code: match outcome {
+                    ManualOutcome::Completed => Some(0),
+                    ManualOutcome::Failed => Some(1),
+                    ManualOutcome::Skipped => None,
+                },
+                stdout: String::new(),
+                stderr: String::new(),
+                manual: Some(outcome),
+                started,
+                ended: SystemTime::now(),
+                duration: start.elapsed(),
+            });
+        }
+    };
+
+    Ok(PhaseReport {
+        phase,
+        command: command.to_string(),
+        executor: executor.name().to_string(),
+        exit_code: output.status.code(),
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        manual: None,
+        started,
+        ended: SystemTime::now(),
+        duration: start.elapsed(),
+    })
+}
+
+/// Run a dependency's check. A non-zero return code means the dependency is missing.
+fn check(
+    command: &str,
+    executor: &str,
+    timeout: Option<Duration>,
+    report: &mut ExecutionReport,
+) -> Result<bool, ArrError> {
+    let phase_report = run_command(command, executor, Phase::Check, timeout)?;
+    let met = phase_report.success();
+
+    report.phases.push(phase_report);
 
-            stderr = if stderr.len() > 0 {
-                stderr
-            } else {
-                std::borrow::Cow::Borrowed("(None)")
+    Ok(met)
+}
+
+fn execute(
+    command: &str,
+    executor: &str,
+    phase: Phase,
+    timeout: Option<Duration>,
+    report: &mut ExecutionReport,
+) -> Result<(), ArrError> {
+    let phase_report = run_command(command, executor, phase, timeout)?;
+    let success = phase_report.success();
+
+    if phase_report.manual == Some(ManualOutcome::Skipped) {
+        report.phases.push(phase_report);
+        return Err(ArrError::ManualStepsSkipped);
+    }
+
+    match success {
+        true => info!("Command executed with a successful return code"),
+        false => {
+            let none = |s: &str| match s.is_empty() {
+                true => "(None)".to_string(),
+                false => s.to_string(),
             };
 
             error!("Unsuccessful return code from the command: `{}`", &command);
-            error!("STDOUT: {}", stdout);
-            error!("STDERR: {}", stderr);
-            Err(ArrError::CommandExecutionFailed)
+            error!("STDOUT: {}", none(&phase_report.stdout));
+            error!("STDERR: {}", none(&phase_report.stderr));
         }
     }
+
+    report.phases.push(phase_report);
+
+    match success {
+        true => Ok(()),
+        false => Err(ArrError::CommandExecutionFailed),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+    use crate::test_support::{corpus, technique_file};
+    use std::{fs, path::Path};
+
+    fn prereq_yaml(marker: &Path) -> String {
+        format!(
+            "attack_technique: T0000.002
+display_name: Prerequisites
+atomic_tests:
+- name: Needs A Marker
+  auto_generated_guid: 00000000-0000-0000-0000-000000000010
+  description: Only runs once the marker exists
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    marker:
+      description: File that must exist
+      type: path
+      default: {}
+  dependencies:
+  - description: |
+      The marker #{{marker}} must exist
+    prereq_command: |
+      test -f #{{marker}}
+    get_prereq_command: |
+      touch #{{marker}}
+  executor:
+    command: |
+      test -f #{{marker}}
+    name: sh
+",
+            marker.to_string_lossy()
+        )
+    }
+
+    #[test]
+    fn check_does_not_get_prereqs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let marker = tmp.path().join("marker");
+        let art_path = corpus("T0000.002", &prereq_yaml(&marker));
+
+        let arr = Arr::new(
+            "T0000.002".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        );
+
+        let statuses = arr.check_prereqs().unwrap();
+        assert_eq!(
+            statuses,
+            vec![PrereqStatus {
+                description: format!("The marker {} must exist", marker.to_string_lossy()),
+                met: false,
+            }]
+        );
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn get_prereqs_only_when_missing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let marker = tmp.path().join("marker");
+        let art_path = corpus("T0000.002", &prereq_yaml(&marker));
+
+        let arr = Arr::new(
+            "T0000.002".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        );
+
+        assert!(arr.get_prereqs().unwrap()[0].met);
+        assert!(marker.exists());
+
+        // the get command must not run again once the check passes
+        fs::write(&marker, "keep me").unwrap();
+        assert!(arr.run().is_ok());
+        assert_eq!(fs::read_to_string(&marker).unwrap(), "keep me");
+    }
+
+    #[test]
+    fn unmet_prereq_stops_the_attack() {
+        let art_path = corpus(
+            "T0000.003",
+            "attack_technique: T0000.003
+display_name: Prerequisites
+atomic_tests:
+- name: Cannot Get Dependency
+  auto_generated_guid: 00000000-0000-0000-0000-000000000011
+  description: The dependency can never be met
+  supported_platforms:
+  - linux
+  - macos
+  dependency_executor_name: sh
+  dependencies:
+  - description: Never there
+    prereq_command: exit 1
+    get_prereq_command: exit 0
+  executor:
+    command: exit 0
+    name: sh
+",
+        );
+
+        let arr = Arr::new(
+            "T0000.003".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        );
+
+        let failure = arr.run().unwrap_err();
+        assert_eq!(
+            failure.source,
+            ArrError::PrerequisiteNotMet("Never there".to_string())
+        );
+
+        // the commands that ran before it stopped are still reported
+        let phases: Vec<(Phase, Option<i32>)> = failure
+            .report
+            .phases
+            .iter()
+            .map(|p| (p.phase, p.exit_code))
+            .collect();
+        assert_eq!(
+            phases,
+            [
+                (Phase::Check, Some(1)),
+                (Phase::Dependency, Some(0)),
+                (Phase::Check, Some(1))
+            ]
+        );
+    }
+
+    #[test]
+    fn report_captures_output() {
+        let art_path = corpus(
+            "T0000.004",
+            "attack_technique: T0000.004
+display_name: Report
+atomic_tests:
+- name: Talks A Lot
+  auto_generated_guid: 00000000-0000-0000-0000-000000000012
+  description: Writes to stdout and stderr
+  supported_platforms:
+  - linux
+  - macos
+  dependencies:
+  - description: Always there
+    prereq_command: |
+      exit 0
+    get_prereq_command: |
+      exit 1
+  executor:
+    command: |
+      echo out; echo err >&2
+    cleanup_command: |
+      echo cleaned
+    name: sh
+",
+        );
+
+        let arr = Arr::new(
+            "T0000.004".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        );
+
+        let report = arr.run().unwrap();
+        assert_eq!(report.technique, "T0000.004");
+        assert_eq!(report.test_number, 1);
+
+        let phases: Vec<Phase> = report.phases.iter().map(|p| p.phase).collect();
+        assert_eq!(phases, vec![Phase::Check, Phase::Attack]);
+
+        let attack = report.phase(Phase::Attack).next().unwrap();
+        assert_eq!(attack.executor, "sh");
+        assert_eq!(attack.exit_code, Some(0));
+        assert_eq!(attack.stdout, "out\n");
+        assert_eq!(attack.stderr, "err\n");
+        assert!(attack.started <= attack.ended);
+
+        let cleanup = arr.cleanup().unwrap();
+        assert_eq!(cleanup.phases.len(), 1);
+        assert_eq!(cleanup.phases[0].phase, Phase::Cleanup);
+        assert_eq!(cleanup.phases[0].stdout, "cleaned\n");
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> HashMap<String, String> {
+        vars.iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn scripts_keep_their_lines() {
+        // PowerShell script block, as used by the Windows atomics
+        let script = "$scriptBlock = {
+    Get-Process | Where-Object { $_.Name -eq \"#{process_name}\" }
+}
+
+& $scriptBlock
+";
+
+        assert_eq!(
+            parse_commands(
+                script,
+                &vars(&[("process_name", "lsass")]).into(),
+                Some("powershell")
+            ),
+            Ok("$scriptBlock = {
+    Get-Process | Where-Object { $_.Name -eq \"lsass\" }
+}
+
+& $scriptBlock
+"
+            .to_string())
+        );
+    }
+
+    #[test]
+    fn multi_line_shell_scripts_run() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("service");
+        let vars = vars(&[
+            ("service_file", &out.to_string_lossy()),
+            ("service_name", "art-systemd-service"),
+        ]);
+
+        // a heredoc, a continued line and an `if` block spread over several lines
+        let script = "cat > #{service_file} <<EOF
+[Unit]
+Description=#{service_name}
+EOF
+echo \\
+  continued >> #{service_file}
+if [ -f #{service_file} ]
+then
+  echo found
+else
+  exit 1
+fi
+";
+
+        let command = parse_commands(script, &vars.into(), Some("sh")).unwrap();
+        let mut report = ExecutionReport::new("T0000.005", 1);
+        execute(&command, "sh", Phase::Attack, None, &mut report).unwrap();
+
+        assert_eq!(report.phases[0].stdout, "found\n");
+        assert_eq!(
+            fs::read_to_string(&out).unwrap(),
+            "[Unit]\nDescription=art-systemd-service\ncontinued\n"
+        );
+    }
+
+    #[test]
+    fn corpus_atomics_run() {
+        // T1082's script spreads `if` blocks and redirections over several lines
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("T1082.txt");
+        let arr = Arr::new(
+            "T1082".to_string(),
+            vars(&[("output_file", &out.to_string_lossy())]),
+            0,
+            vec![PathBuf::from("atomic-red-team-master")],
+        );
+
+        let report = arr.run().unwrap();
+        let uname = Command::new("uname").arg("-a").output().unwrap();
+        let written = fs::read_to_string(&out).unwrap();
+
+        assert!(written.starts_with(&*String::from_utf8_lossy(&uname.stdout)));
+        assert_eq!(report.phases.last().unwrap().stdout, written);
+
+        arr.cleanup().unwrap();
+        assert!(!out.exists());
+    }
+
+    #[test]
+    fn manual_tests_use_their_steps() {
+        let yaml: AtomicReadTeamTechnique = serde_yaml::from_str(
+            "attack_technique: T0000.006
+display_name: Manual
+atomic_tests:
+- name: By Hand
+  auto_generated_guid: 00000000-0000-0000-0000-000000000013
+  description: Performed by an operator
+  supported_platforms:
+  - windows
+  input_arguments:
+    user:
+      description: Account to inspect
+      type: string
+      default: Administrator
+  executor:
+    steps: |
+      1. Open Computer Management
+      2. Inspect the #{user} account
+    name: manual
+",
+        )
+        .unwrap();
+
+        assert_eq!(
+            get_attack_command(&yaml.atomic_tests[0], &vars(&[("user", "art")]).into()),
+            Ok((
+                "1. Open Computer Management\n2. Inspect the art account\n".to_string(),
+                "manual".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn plan_runs_nothing() {
+        let tmp = tempfile::tempdir().unwrap();
+        let marker = tmp.path().join("marker");
+        let art_path = corpus("T0000.002", &prereq_yaml(&marker));
+
+        let arr = Arr::new(
+            "T0000.002".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        );
+
+        let plan = arr.plan().unwrap();
+        let marker = marker.to_string_lossy();
+
+        assert_eq!(plan.test_name, "Needs A Marker");
+        assert_eq!(plan.guid, "00000000-0000-0000-0000-000000000010");
+        assert_eq!(plan.variables.get("marker"), Some(&marker.to_string()));
+        assert_eq!(plan.prerequisites.len(), 1);
+        assert_eq!(plan.prerequisites[0].executor, "sh");
+        assert_eq!(
+            plan.prerequisites[0].program.as_deref(),
+            Some("sh -c <script>")
+        );
+        assert_eq!(
+            plan.prerequisites[0].get_command,
+            format!("touch {}\n", marker)
+        );
+        assert_eq!(plan.attack.command, format!("test -f {}\n", marker));
+        assert_eq!(plan.attack.program.as_deref(), Some("sh -c <script>"));
+        assert_eq!(plan.cleanup, None);
+
+        assert!(!Path::new(marker.as_ref()).exists());
+    }
+
+    #[test]
+    fn plans_do_not_need_root() {
+        let arr = Arr::new(
+            "T1574.006".to_string(),
+            HashMap::new(),
+            0,
+            vec![PathBuf::from("atomic-red-team-master")],
+        );
+
+        let plan = arr.plan().unwrap();
+
+        assert!(plan.elevation_required);
+        assert_eq!(plan.attack.program.as_deref(), Some("bash -c <script>"));
+        assert!(plan.to_string().contains("Requires elevation to run"));
+    }
+
+    #[test]
+    fn info_shows_raw_and_substituted_commands() {
+        let art_path = corpus("T0000.002", &prereq_yaml(Path::new("/tmp/marker")));
+
+        let info = technique_info(
+            "T0000.002",
+            Some(&"1".parse().unwrap()),
+            vars(&[("marker", "PathToAtomicsFolder/marker")]),
+            &VarSources::default(),
+            false,
+            &[art_path.path().to_owned()],
+            None,
+        )
+        .unwrap();
+
+        let marker = art_path.path().join("atomics/marker");
+        let test = &info.tests[0];
+
+        assert_eq!(info.display_name, "Prerequisites");
+        assert_eq!(test.input_arguments[0].default, "/tmp/marker");
+        assert_eq!(test.input_arguments[0].value, marker.to_string_lossy());
+        assert_eq!(test.attack.raw, "test -f #{marker}\n");
+        assert_eq!(
+            test.attack.substituted,
+            Some(format!("test -f {}\n", marker.to_string_lossy()))
+        );
+        assert_eq!(test.attack.error, None);
+        assert_eq!(test.dependencies[0].get.raw, "touch #{marker}\n");
+        assert_eq!(test.cleanup, None);
+    }
+
+    #[test]
+    fn info_keeps_substitution_errors_apart() {
+        let art_path = corpus(
+            "T0000.002",
+            "attack_technique: T0000.002
+display_name: Unresolved
+atomic_tests:
+- name: Misspelled
+  auto_generated_guid: 00000000-0000-0000-0000-000000000022
+  description: Refers to a variable it doesn't take
+  supported_platforms:
+  - linux
+  executor:
+    command: 'echo #{mising}'
+    name: sh
+",
+        );
+
+        let info = technique_info(
+            "T0000.002",
+            None,
+            HashMap::new(),
+            &VarSources::default(),
+            false,
+            &[art_path.path().to_owned()],
+            None,
+        )
+        .unwrap();
+        let attack = &info.tests[0].attack;
+
+        assert_eq!(attack.substituted, None);
+        assert_eq!(
+            attack.error,
+            Some(ArrError::ArgValueNotFound("mising".to_string()).to_string())
+        );
+        assert!(info.to_string().contains("cannot substitute:"));
+    }
+
+    #[test]
+    fn atomics_folder_is_the_owning_root() {
+        let technique = |name: &str, guid: &str| {
+            format!(
+                "attack_technique: T0000.002
+display_name: Roots
+atomic_tests:
+- name: {}
+  auto_generated_guid: {}
+  description: Prints its atomics folder
+  supported_platforms:
+  - linux
+  executor:
+    command: cat PathToAtomicsFolder/payload
+    name: sh
+",
+                name, guid
+            )
+        };
+
+        let upstream = corpus(
+            "T0000.002",
+            &technique("Upstream", "00000000-0000-0000-0000-000000000020"),
+        );
+        let custom = corpus(
+            "T0000.002",
+            &technique("Custom", "00000000-0000-0000-0000-000000000021"),
+        );
+        let roots = vec![custom.path().to_owned(), upstream.path().to_owned()];
+
+        let plan = |test_num| {
+            Arr::new(
+                "T0000.002".to_string(),
+                HashMap::new(),
+                test_num,
+                roots.clone(),
+            )
+            .plan()
+            .unwrap()
+        };
+
+        let upstream_plan = plan(0);
+        let custom_plan = plan(1);
+
+        assert_eq!(upstream_plan.test_name, "Upstream");
+        assert_eq!(
+            upstream_plan.attack.command,
+            format!("cat {}/atomics/payload", upstream.path().to_string_lossy())
+        );
+        assert_eq!(custom_plan.test_name, "Custom");
+        assert_eq!(
+            custom_plan.attack.command,
+            format!("cat {}/atomics/payload", custom.path().to_string_lossy())
+        );
+    }
+
+    #[test]
+    fn builtins_are_resolved_like_invoke_atomicredteam() {
+        let art_path = corpus(
+            "T0000.023",
+            "attack_technique: T0000.023
+display_name: Built-ins
+atomic_tests:
+- name: Paths
+  auto_generated_guid: 00000000-0000-0000-0000-000000000023
+  description: Uses the built-in variables
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    payload:
+      description: A payload
+      type: path
+      default: PathToPayloads\\T0000.023\\bin\\payload
+  executor:
+    command: |
+      cat #{payload} '#{PathToTechniqueFolder}/src' \"$PathToAtomicsFolder/x\"
+      echo $env:PathToAtomicsFolder
+    name: sh
+",
+        );
+
+        let plan = Arr::new(
+            "T0000.023".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        )
+        .plan()
+        .unwrap();
+
+        let atomics = art_path
+            .path()
+            .join("atomics")
+            .to_string_lossy()
+            .to_string();
+        assert_eq!(
+            plan.attack.command,
+            format!(
+                "cat {0}/T0000.023/bin/payload '{0}/T0000.023/src' \"{0}/x\"\n\
+                 echo $env:PathToAtomicsFolder\n",
+                atomics
+            )
+        );
+    }
+
+    fn defaults_yaml(log_default: &str) -> String {
+        "attack_technique: T0000.024
+display_name: Defaults
+atomic_tests:
+- name: Nested Defaults
+  auto_generated_guid: 00000000-0000-0000-0000-000000000024
+  description: Defaults that use other arguments
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    output_file:
+      description: Where to write
+      type: path
+      default: '#{output_dir}/#{file_name}'
+    output_dir:
+      description: The directory to write in
+      type: path
+      default: /tmp
+    file_name:
+      description: The file to write
+      type: string
+      default: arr-024
+    log:
+      description: The log
+      type: string
+      default: 'LOG'
+  executor:
+    command: 'echo #{log} > #{output_file}'
+    name: sh
+"
+        .replace("LOG", log_default)
+    }
+
+    #[test]
+    fn defaults_are_resolved_in_order() {
+        let art_path = corpus("T0000.024", &defaults_yaml("#{output_file}.log"));
+        let roots = vec![art_path.path().to_owned()];
+        let plan = |vars: HashMap<String, String>, var_sources: VarSources| {
+            Arr::new("T0000.024".to_string(), vars, 0, roots.clone())
+                .with_var_sources(var_sources)
+                .plan()
+        };
+
+        assert_eq!(
+            plan(HashMap::new(), VarSources::default())
+                .unwrap()
+                .attack
+                .command,
+            "echo /tmp/arr-024.log > /tmp/arr-024"
+        );
+
+        // --set-var beats the environment, which beats the vars file
+        let var_sources = VarSources {
+            file: vars(&[("file_name", "from file"), ("output_dir", "/var/tmp")]).into(),
+            env: vars(&[("file_name", "from-env"), ("not_an_argument", "x")]),
+        };
+        let command = |vars| plan(vars, var_sources.clone()).unwrap().attack.command;
+
+        assert_eq!(
+            command(HashMap::new()),
+            "echo /var/tmp/from-env.log > /var/tmp/from-env"
+        );
+        assert_eq!(
+            command(vars(&[("file_name", "a b")])),
+            "echo /var/tmp/'a b'.log > /var/tmp/'a b'"
+        );
+
+        // only the user's values are quoted, and the shell still expands the rest of the default
+        let art_path = corpus(
+            "T0000.024",
+            &defaults_yaml("$ARR_UNSET_VARIABLE/#{file_name}"),
+        );
+        assert_eq!(
+            Arr::new(
+                "T0000.024".to_string(),
+                vars(&[("file_name", "a b")]),
+                0,
+                vec![art_path.path().to_owned()]
+            )
+            .plan()
+            .unwrap()
+            .attack
+            .command,
+            "echo $ARR_UNSET_VARIABLE/'a b' > /tmp/'a b'"
+        );
+
+        let art_path = corpus("T0000.024", &defaults_yaml("#{log}"));
+        assert_eq!(
+            Arr::new(
+                "T0000.024".to_string(),
+                HashMap::new(),
+                0,
+                vec![art_path.path().to_owned()]
+            )
+            .plan(),
+            Err(ArrError::ArgumentCycle(vec![
+                "log".to_string(),
+                "log".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn missing_test_is_an_error() {
+        let tmp = tempfile::tempdir().unwrap();
+        let art_path = corpus("T0000.002", &prereq_yaml(&tmp.path().join("marker")));
+
+        let arr = Arr::new(
+            "T0000.002".to_string(),
+            HashMap::new(),
+            41,
+            vec![art_path.path().to_owned()],
+        );
+
+        let expected = || ArrError::TestNotFound {
+            technique: "T0000.002".to_string(),
+            requested: "42".to_string(),
+            available: vec!["1: Needs A Marker".to_string()],
+        };
+
+        assert_eq!(arr.plan(), Err(expected()));
+        assert_eq!(arr.run().map_err(|f| f.source).err(), Some(expected()));
+        assert_eq!(arr.cleanup().map_err(|f| f.source).err(), Some(expected()));
+    }
+
+    #[test]
+    fn unresolved_variables_are_all_reported() {
+        let art_path = corpus(
+            "T0000.022",
+            "attack_technique: T0000.022
+display_name: Unresolved
+atomic_tests:
+- name: Typos
+  auto_generated_guid: 00000000-0000-0000-0000-000000000022
+  description: Refers to arguments it does not have
+  supported_platforms:
+  - linux
+  - macos
+  - windows
+  input_arguments:
+    output_file:
+      description: Where to write
+      type: path
+      default: /tmp/arr-022
+  dependencies:
+  - description: Has a tool
+    prereq_command: 'command -v #{tool}'
+    get_prereq_command: exit 0
+  executor:
+    command: |
+      echo start
+      echo #{output_file} > #{outputfile}
+    cleanup_command: 'rm -f #{output_file} #{log}'
+    name: sh
+",
+        );
+
+        let arr = Arr::new(
+            "T0000.022".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        );
+
+        let unresolved =
+            |name: &str, phase, dependency, line, column, closest: Option<&str>| Unresolved {
+                name: name.to_string(),
+                phase,
+                dependency,
+                line,
+                column,
+                closest: closest.map(String::from),
+            };
+
+        let err = arr.plan().unwrap_err();
+        assert_eq!(
+            err,
+            ArrError::UnresolvedVariables {
+                unresolved: vec![
+                    unresolved("tool", Phase::Check, Some(1), 1, 12, None),
+                    unresolved(
+                        "outputfile",
+                        Phase::Attack,
+                        None,
+                        2,
+                        23,
+                        Some("output_file")
+                    ),
+                    unresolved("log", Phase::Cleanup, None, 1, 22, None),
+                ],
+                available: vec!["output_file".to_string()],
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            "3 variables have no value:
+    check of dependency 1 1:12: `#{tool}`
+    attack 2:23: `#{outputfile}`, did you mean `output_file`?
+    cleanup 1:22: `#{log}`
+The test's input arguments are: output_file"
+        );
+        assert_eq!(arr.run().map_err(|f| f.source).err(), Some(err));
+    }
+
+    fn ledger_yaml(attack: &str) -> String {
+        format!(
+            "attack_technique: T0000.007
+display_name: Ledger
+atomic_tests:
+- name: Leaves A File Behind
+  auto_generated_guid: 00000000-0000-0000-0000-000000000014
+  description: Creates a file that the cleanup removes
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    output_file:
+      description: File to create
+      type: path
+      default: /tmp/arr-ledger-test
+  executor:
+    command: |
+      {}
+    cleanup_command: |
+      rm #{{output_file}}
+    name: sh
+",
+            attack
+        )
+    }
+
+    #[test]
+    fn ledger_replays_pending_cleanups() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("attacked");
+        let ledger = Ledger::new(tmp.path().join("ledger.json"));
+        let art_path = corpus("T0000.007", &ledger_yaml("touch #{output_file}"));
+
+        let arr = Arr::new(
+            "T0000.007".to_string(),
+            vars(&[("output_file", &out.to_string_lossy())]),
+            0,
+            vec![art_path.path().to_owned()],
+        )
+        .with_ledger(ledger.clone());
+
+        arr.run().unwrap();
+        assert!(out.exists());
+
+        let entries = ledger.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].guid, "00000000-0000-0000-0000-000000000014");
+        assert_eq!(
+            entries[0].vars["output_file"],
+            out.to_string_lossy().to_string()
+        );
+
+        // the replay uses the recorded variables, not the defaults
+        let results = cleanup_pending(
+            &ledger,
+            &[art_path.path().to_owned()],
+            None,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].1.is_ok());
+        assert!(!out.exists());
+        assert_eq!(ledger.entries(), Ok(vec![]));
+    }
+
+    #[test]
+    fn ledger_keeps_the_attacks_defaults() {
+        let tmp = tempfile::tempdir().unwrap();
+        let attacked = tmp.path().join("attacked").to_string_lossy().to_string();
+        let changed = tmp.path().join("changed").to_string_lossy().to_string();
+        let yaml = |default: &str| {
+            ledger_yaml("touch #{output_file}").replace("/tmp/arr-ledger-test", default)
+        };
+
+        let ledger = Ledger::new(tmp.path().join("ledger.json"));
+        let art_path = corpus("T0000.007", &yaml(&attacked));
+
+        Arr::new(
+            "T0000.007".to_string(),
+            HashMap::new(),
+            0,
+            vec![art_path.path().to_owned()],
+        )
+        .with_ledger(ledger.clone())
+        .run()
+        .unwrap();
+
+        let entries = ledger.entries().unwrap();
+        assert_eq!(entries[0].vars["output_file"], attacked);
+        assert_eq!(entries[0].set_vars(), HashMap::new());
+
+        // the cleanup removes what the attack created, not what the test now defaults to
+        fs::write(
+            technique_file(&art_path.path().join("atomics"), "T0000.007"),
+            yaml(&changed),
+        )
+        .unwrap();
+        fs::write(&changed, "").unwrap();
+
+        let results = cleanup_pending(
+            &ledger,
+            &[art_path.path().to_owned()],
+            None,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert!(results[0].1.is_ok());
+        assert!(!Path::new(&attacked).exists());
+        assert!(Path::new(&changed).exists());
+        assert_eq!(ledger.entries(), Ok(vec![]));
+    }
+
+    #[test]
+    fn removed_tests_are_not_cleaned_up() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("attacked");
+        let ledger = Ledger::new(tmp.path().join("ledger.json"));
+        let art_path = corpus("T0000.007", &ledger_yaml("touch #{output_file}"));
+
+        Arr::new(
+            "T0000.007".to_string(),
+            vars(&[("output_file", &out.to_string_lossy())]),
+            0,
+            vec![art_path.path().to_owned()],
+        )
+        .with_ledger(ledger.clone())
+        .run()
+        .unwrap();
+
+        // another test takes the removed test's place
+        fs::write(
+            technique_file(&art_path.path().join("atomics"), "T0000.007"),
+            ledger_yaml("touch #{output_file}").replace("000000000014", "000000000015"),
+        )
+        .unwrap();
+
+        let results = cleanup_pending(
+            &ledger,
+            &[art_path.path().to_owned()],
+            None,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert!(matches!(results[0].1, Err(ArrError::TestNotFound { .. })));
+        assert!(out.exists());
+        assert_eq!(ledger.entries().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn set_vars_cannot_inject_commands() {
+        let tmp = tempfile::tempdir().unwrap();
+        // unquoted, this would also touch `arr-injected` in the working directory
+        let injected = Path::new("arr-injected");
+        let out = format!(
+            "{} it's; touch {}",
+            tmp.path().join("attacked").to_string_lossy(),
+            injected.to_string_lossy()
+        );
+        let ledger = Ledger::new(tmp.path().join("ledger.json"));
+        let art_path = corpus("T0000.007", &ledger_yaml("touch #{output_file}"));
+
+        Arr::new(
+            "T0000.007".to_string(),
+            vars(&[("output_file", &out)]),
+            0,
+            vec![art_path.path().to_owned()],
+        )
+        .with_ledger(ledger.clone())
+        .run()
+        .unwrap();
+
+        assert!(Path::new(&out).exists());
+        assert!(!injected.exists());
+
+        // the replayed cleanup quotes the value the same way
+        let results = cleanup_pending(
+            &ledger,
+            &[art_path.path().to_owned()],
+            None,
+            Timeouts::default(),
+        )
+        .unwrap();
+        assert!(results[0].1.is_ok());
+        assert!(!Path::new(&out).exists());
+    }
+
+    #[test]
+    fn cleanup_on_failure() {
+        let tmp = tempfile::tempdir().unwrap();
+        let out = tmp.path().join("attacked");
+        let ledger = Ledger::new(tmp.path().join("ledger.json"));
+        let art_path = corpus("T0000.007", &ledger_yaml("touch #{output_file}; exit 1"));
+
+        let arr = Arr::new(
+            "T0000.007".to_string(),
+            vars(&[("output_file", &out.to_string_lossy())]),
+            0,
+            vec![art_path.path().to_owned()],
+        )
+        .with_ledger(ledger.clone())
+        .with_cleanup_on_failure(true);
+
+        let failure = arr.run().unwrap_err();
+        assert_eq!(failure.source, ArrError::CommandExecutionFailed);
+        assert!(!out.exists());
+        assert_eq!(ledger.entries(), Ok(vec![]));
+
+        // the failed attack's output is kept, along with the cleanup it led to
+        let attack = failure.report.phase(Phase::Attack).next().unwrap();
+        assert_eq!(attack.exit_code, Some(1));
+        assert_eq!(failure.report.phase(Phase::Cleanup).count(), 1);
+    }
+
+    #[test]
+    fn set_vars_are_checked() {
+        let art_path = corpus(
+            "T0000.020",
+            "attack_technique: T0000.020
+display_name: Typed Arguments
+atomic_tests:
+- name: Counts
+  auto_generated_guid: 00000000-0000-0000-0000-000000000200
+  description: Takes an integer
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    count:
+      description: How many
+      type: Integer
+      default: 1
+  executor:
+    command: 'echo #{count}'
+    name: sh
+- name: Downloads
+  auto_generated_guid: 00000000-0000-0000-0000-000000000201
+  description: Takes a URL
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    remote_url:
+      description: Where from
+      type: url
+      default: https://example.com/payload
+  executor:
+    command: 'echo #{remote_url}'
+    name: sh
+",
+        );
+        let art_paths = vec![art_path.path().to_owned()];
+        let vars = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        let plan = |tests: &str, pairs: &[(&str, &str)]| {
+            Arr::select(
+                "T0000.020",
+                &tests.parse().unwrap(),
+                vars(pairs),
+                art_paths.clone(),
+                None,
+            )
+            .and_then(|tests| tests.iter().map(Arr::plan).collect::<Result<Vec<_>, _>>())
+        };
+
+        assert_eq!(
+            plan("1", &[("cuont", "3")]).unwrap_err(),
+            ArrError::UnknownArgument {
+                name: "cuont".to_string(),
+                closest: Some("count".to_string()),
+                available: vec!["count".to_string()],
+            }
+        );
+        assert_eq!(
+            plan("1", &[("count", "three")]).unwrap_err(),
+            ArrError::InvalidArgValue {
+                name: "count".to_string(),
+                value: "three".to_string(),
+                expected: ArgType::Integer,
+            }
+        );
+        assert!(matches!(
+            plan("2", &[("remote_url", "example.com")]),
+            Err(ArrError::InvalidArgValue { .. })
+        ));
+
+        // each test gets the variables it takes, coerced to its type
+        let plans = plan("1-2", &[("count", " 3 "), ("remote_url", "ftp://host/x")]).unwrap();
+        assert_eq!(plans[0].attack.command.trim(), "echo 3");
+        assert_eq!(plans[1].attack.command.trim(), "echo ftp://host/x");
+    }
 }
@@ -0,0 +1,472 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use serde::Serialize;
+
+use crate::{
+    argument::ArgType,
+    builtin::BUILTINS,
+    error::{ArrError, SourceError},
+    executor::executor_for,
+    find_file::{all_techniques, find_atomics_dir},
+    parse_command::variables,
+    parse_yaml::{AtomicReadTeamTechnique, AtomicTest},
+    selector::is_guid,
+};
+
+/// The platforms a test may list in `supported_platforms`.
+pub const PLATFORMS: &[&str] = &[
+    "windows",
+    "macos",
+    "linux",
+    "office-365",
+    "azure-ad",
+    "google-workspace",
+    "saas",
+    "iaas",
+    "iaas:aws",
+    "iaas:azure",
+    "iaas:gcp",
+    "containers",
+    "esxi",
+];
+
+/// A problem with a technique file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LintIssue {
+    pub path: PathBuf,
+    /// One-based, if the problem can be pinned to a line
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl fmt::Display for LintIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path.display(), line, self.message),
+            None => write!(f, "{}: {}", self.path.display(), self.message),
+        }
+    }
+}
+
+/// Check every technique under the path, reporting all the problems rather than the first.
+pub fn lint(art_path: &Path) -> Result<Vec<LintIssue>, ArrError> {
+    let atomics_dir = find_atomics_dir(art_path)?;
+    let used_guids_path = atomics_dir.join("used_guids.txt");
+    let used_guids: HashSet<String> = fs::read_to_string(&used_guids_path)
+        .map_err(|e| {
+            ArrError::CannotReadFile(SourceError::unreadable(used_guids_path.clone(), &e))
+        })?
+        .lines()
+        .map(|l| l.trim().to_lowercase())
+        .collect();
+
+    let mut paths: Vec<PathBuf> = all_techniques(art_path).map(|f| f.into_path()).collect();
+    paths.sort();
+
+    let mut issues = Vec::new();
+    // where each GUID was first seen
+    let mut seen: HashMap<String, (PathBuf, usize)> = HashMap::new();
+
+    for path in paths {
+        info!("Linting {}", path.to_string_lossy());
+
+        let mut issue = |line: Option<usize>, message: String| {
+            issues.push(LintIssue {
+                path: path.clone(),
+                line,
+                message,
+            })
+        };
+
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) => {
+                issue(None, format!("cannot read the file: {}", e));
+                continue;
+            }
+        };
+
+        let yaml = match serde_yaml::from_str::<AtomicReadTeamTechnique>(&text) {
+            Ok(yaml) => yaml,
+            Err(e) => {
+                let e = SourceError::from_yaml(path.clone(), &text, &e);
+                issue(e.line, format!("cannot parse: {}", e.message));
+                continue;
+            }
+        };
+
+        let lines = Lines::new(&text);
+
+        for (i, test) in yaml.atomic_tests.iter().enumerate() {
+            let guid = test.auto_generated_guid.trim().to_lowercase();
+            let line = lines.find(i, |l| l.contains(test.auto_generated_guid.trim()));
+
+            if !is_guid(&guid) {
+                issue(
+                    Some(line),
+                    format!("malformed GUID `{}`", test.auto_generated_guid),
+                );
+            } else {
+                match seen.get(&guid) {
+                    Some((first, first_line)) => issue(
+                        Some(line),
+                        format!(
+                            "GUID {} is already used at {}:{}",
+                            guid,
+                            first.display(),
+                            first_line
+                        ),
+                    ),
+                    None => {
+                        seen.insert(guid.clone(), (path.clone(), line));
+                    }
+                }
+
+                if !used_guids.contains(&guid) {
+                    issue(
+                        Some(line),
+                        format!("GUID {} is not in {}", guid, used_guids_path.display()),
+                    );
+                }
+            }
+
+            for name in std::iter::once(&test.executor.name).chain(&test.dependency_executor_name) {
+                if executor_for(name).is_err() {
+                    let line = lines.find(i, |l| l.trim().ends_with(&format!(": {}", name)));
+                    issue(Some(line), format!("unknown executor `{}`", name));
+                }
+            }
+
+            for platform in &test.supported_platforms {
+                if !PLATFORMS.contains(&platform.as_str()) {
+                    let line = lines.find(i, |l| l.trim() == format!("- {}", platform));
+                    issue(Some(line), format!("unknown platform `{}`", platform));
+                }
+            }
+
+            let has_dependencies = test.dependencies.as_ref().is_some_and(|d| !d.is_empty());
+            if has_dependencies && test.dependency_executor_name.is_none() {
+                let line = lines.find(i, |l| l.trim() == "dependencies:");
+                issue(
+                    Some(line),
+                    "dependencies without a dependency_executor_name".to_string(),
+                );
+            }
+
+            let mut used: HashSet<&str> = HashSet::new();
+            for text in texts(test) {
+                for name in variables(text) {
+                    if used.insert(name)
+                        && !test.input_arguments.contains_key(name)
+                        && !BUILTINS.contains(&name)
+                    {
+                        let line = lines.find(i, |l| l.contains(&format!("#{{{}}}", name)));
+                        issue(
+                            Some(line),
+                            format!("`#{{{}}}` has no matching input argument", name),
+                        );
+                    }
+                }
+            }
+
+            let mut arguments: Vec<&String> = test.input_arguments.keys().collect();
+            arguments.sort();
+            for name in arguments {
+                if let ArgType::Unknown(arg_type) = &test.input_arguments[name].arg_type {
+                    let line = lines.find(i, |l| {
+                        l.trim().strip_prefix("type:").map(str::trim) == Some(arg_type.as_str())
+                    });
+                    issue(
+                        Some(line),
+                        format!(
+                            "input argument `{}` has unknown type `{}`, expected string, path, \
+                             url, integer, float or bool",
+                            name, arg_type
+                        ),
+                    );
+                }
+
+                if !used.contains(name.as_str()) {
+                    let line = lines.find(i, |l| l.trim() == format!("{}:", name));
+                    issue(
+                        Some(line),
+                        format!("input argument `{}` is never used", name),
+                    );
+                }
+            }
+
+            if test.executor.elevation_required != Some(true) {
+                if let Some(hint) = texts(test).find_map(elevation_hint) {
+                    let line = lines.find(i, |l| elevation_hint(l).is_some());
+                    issue(
+                        Some(line),
+                        format!("uses {} but elevation_required is not true", hint),
+                    );
+                }
+            }
+        }
+    }
+
+    Ok(issues)
+}
+
+/// Everything in a test that may reference an input argument.
+fn texts(test: &AtomicTest) -> impl Iterator<Item = &str> {
+    let executor = [
+        &test.executor.command,
+        &test.executor.cleanup_command,
+        &test.executor.steps,
+    ]
+    .into_iter()
+    .flatten();
+
+    let dependencies = test
+        .dependencies
+        .iter()
+        .flatten()
+        .flat_map(|d| [&d.description, &d.prereq_command, &d.get_prereq_command]);
+
+    let mut arguments: Vec<_> = test.input_arguments.iter().collect();
+    arguments.sort_by_key(|(name, _)| *name);
+    let defaults = arguments.into_iter().map(|(_, arg)| &arg.default);
+
+    executor
+        .chain(dependencies)
+        .chain(defaults)
+        .map(String::as_str)
+}
+
+/// What in a command says it needs to run elevated.
+fn elevation_hint(text: &str) -> Option<&'static str> {
+    if text
+        .split(|c: char| c.is_whitespace() || c == ';' || c == '(' || c == '\'' || c == '"')
+        .any(|word| word == "sudo")
+    {
+        Some("sudo")
+    } else if text
+        .to_lowercase()
+        .contains("#requires -runasadministrator")
+    {
+        Some("#Requires -RunAsAdministrator")
+    } else {
+        None
+    }
+}
+
+/// A technique file's lines, and where each test starts.
+struct Lines<'a> {
+    lines: Vec<&'a str>,
+    tests: Vec<usize>,
+}
+
+impl<'a> Lines<'a> {
+    fn new(text: &'a str) -> Self {
+        let lines: Vec<&str> = text.lines().collect();
+        let mut tests = Vec::new();
+        let mut indent = None;
+
+        let start = lines
+            .iter()
+            .position(|l| l.starts_with("atomic_tests:"))
+            .map_or(0, |i| i + 1);
+
+        for (i, line) in lines.iter().enumerate().skip(start) {
+            let trimmed = line.trim_start();
+            let this_indent = line.len() - trimmed.len();
+
+            if trimmed.starts_with("- ") && indent.unwrap_or(this_indent) == this_indent {
+                indent = Some(this_indent);
+                tests.push(i);
+            }
+        }
+
+        Self { lines, tests }
+    }
+
+    /// The one-based line of the first match in a test, or of the test itself.
+    fn find(&self, test: usize, matches: impl Fn(&str) -> bool) -> usize {
+        let start = self.tests.get(test).copied().unwrap_or(0);
+        let end = self
+            .tests
+            .get(test + 1)
+            .copied()
+            .unwrap_or(self.lines.len());
+
+        (start..end)
+            .find(|&i| matches(self.lines[i]))
+            .unwrap_or(start)
+            + 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::{corpus, technique_file, write_atomics};
+
+    const TECHNIQUE: &str = "attack_technique: T0000.018
+display_name: Lint
+atomic_tests:
+- name: Clean
+  auto_generated_guid: 00000000-0000-0000-0000-000000000181
+  description: Nothing wrong here
+  supported_platforms:
+  - linux
+  input_arguments:
+    file:
+      description: A file
+      type: path
+      default: /tmp/lint
+  executor:
+    command: |
+      sudo touch #{file}
+    name: sh
+    elevation_required: true
+- name: Everything Wrong
+  auto_generated_guid: 00000000-0000-0000-0000-000000000181
+  description: Lots wrong here
+  supported_platforms:
+  - plan9
+  input_arguments:
+    unused:
+      description: Never used
+      type: string
+      default: nothing
+  dependencies:
+  - description: Needs a thing
+    prereq_command: exit 0
+    get_prereq_command: exit 0
+  executor:
+    command: |
+      sudo cat #{missing}
+    name: fish
+- name: Bad GUID
+  auto_generated_guid: not-a-guid
+  description: Unparseable GUID
+  supported_platforms:
+  - linux
+  executor:
+    command: id
+    name: sh
+- name: Unlisted
+  auto_generated_guid: 00000000-0000-0000-0000-000000000182
+  description: Missing from used_guids.txt
+  supported_platforms:
+  - linux
+  executor:
+    command: id
+    name: sh
+";
+
+    #[test]
+    fn reports_every_problem() {
+        let art_path = tempfile::tempdir().unwrap();
+        let atomics = write_atomics(
+            art_path.path(),
+            &[
+                ("T0000.018", TECHNIQUE),
+                (
+                    "T0000.019",
+                    "attack_technique: T0000.019\natomic_tests: 3\n",
+                ),
+            ],
+            "00000000-0000-0000-0000-000000000181\n",
+        );
+        let file = technique_file(&atomics, "T0000.018");
+        let broken = technique_file(&atomics, "T0000.019");
+
+        let issues: Vec<String> = lint(art_path.path())
+            .unwrap()
+            .iter()
+            .map(|issue| issue.to_string())
+            .collect();
+
+        let at = |line: usize, message: &str| format!("{}:{}: {}", file.display(), line, message);
+        let used_guids = atomics.join("used_guids.txt");
+
+        assert_eq!(
+            issues,
+            [
+                at(
+                    20,
+                    &format!(
+                        "GUID 00000000-0000-0000-0000-000000000181 is already used at {}:5",
+                        file.display()
+                    )
+                ),
+                at(36, "unknown executor `fish`"),
+                at(23, "unknown platform `plan9`"),
+                at(29, "dependencies without a dependency_executor_name"),
+                at(35, "`#{missing}` has no matching input argument"),
+                at(25, "input argument `unused` is never used"),
+                at(35, "uses sudo but elevation_required is not true"),
+                at(38, "malformed GUID `not-a-guid`"),
+                at(
+                    46,
+                    &format!(
+                        "GUID 00000000-0000-0000-0000-000000000182 is not in {}",
+                        used_guids.display()
+                    )
+                ),
+                format!(
+                    "{}:2: cannot parse: atomic_tests: invalid type: integer `3`, \
+                     expected a sequence",
+                    broken.display()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_types_are_reported() {
+        let art_path = corpus(
+            "T0000.020",
+            "attack_technique: T0000.020
+display_name: Types
+atomic_tests:
+- name: Unknown Type
+  auto_generated_guid: 00000000-0000-0000-0000-000000000200
+  description: Takes a number
+  supported_platforms:
+  - linux
+  input_arguments:
+    count:
+      description: How many
+      type: Number
+      default: 3
+  executor:
+    command: 'seq #{count}'
+    name: sh
+",
+        );
+        fs::write(
+            art_path.path().join("atomics/used_guids.txt"),
+            "00000000-0000-0000-0000-000000000200\n",
+        )
+        .unwrap();
+
+        let issues: Vec<String> = lint(art_path.path())
+            .unwrap()
+            .iter()
+            .map(|issue| issue.message.clone())
+            .collect();
+
+        assert_eq!(
+            issues,
+            [
+                "input argument `count` has unknown type `Number`, expected string, path, \
+              url, integer, float or bool"
+            ]
+        );
+    }
+
+    #[test]
+    fn fixture_is_clean() {
+        assert_eq!(lint(Path::new("atomic-red-team-master")), Ok(vec![]));
+    }
+}
@@ -0,0 +1,167 @@
+use std::path::{Path, PathBuf};
+
+use log::error;
+use serde::Serialize;
+
+use crate::{
+    error::ArrError,
+    parse_yaml::{AtomicReadTeamTechnique, AtomicTest},
+    roots::Roots,
+};
+
+/// Narrows `list_tests` down. Every filter that is set must match.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TestFilter {
+    pub platform: Option<String>,
+    pub executor: Option<String>,
+    pub elevation_required: Option<bool>,
+    /// Words that must all appear in the technique or test's name or description
+    pub search: Option<String>,
+}
+
+impl TestFilter {
+    fn matches(&self, technique: &AtomicReadTeamTechnique, test: &AtomicTest) -> bool {
+        let platform = self.platform.as_ref().is_none_or(|platform| {
+            test.supported_platforms
+                .iter()
+                .any(|p| p.trim().eq_ignore_ascii_case(platform.trim()))
+        });
+
+        let executor = self
+            .executor
+            .as_ref()
+            .is_none_or(|executor| test.executor.name.eq_ignore_ascii_case(executor.trim()));
+
+        let elevation = self
+            .elevation_required
+            .is_none_or(|required| elevation_required(test) == required);
+
+        let search = self.search.as_ref().is_none_or(|search| {
+            let text = [
+                &technique.attack_technique,
+                &technique.display_name,
+                &test.name,
+                &test.description,
+            ]
+            .map(|s| s.to_lowercase())
+            .join("\n");
+
+            search
+                .split_whitespace()
+                .all(|word| text.contains(&word.to_lowercase()))
+        });
+
+        platform && executor && elevation && search
+    }
+}
+
+/// One line of `arr ls`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TestSummary {
+    pub technique: String,
+    pub display_name: String,
+    pub test_number: usize,
+    pub test_name: String,
+    pub guid: String,
+    pub platforms: Vec<String>,
+    pub executor: String,
+    pub elevation_required: bool,
+}
+
+/// Every test in the roots that the filter matches, in technique order.
+pub fn list_tests(
+    art_paths: &[PathBuf],
+    index_dir: Option<&Path>,
+    filter: &TestFilter,
+) -> Result<Vec<TestSummary>, ArrError> {
+    let roots = Roots::open(art_paths, index_dir)?;
+    let mut tests = Vec::new();
+
+    for id in roots.techniques() {
+        let technique = match roots.technique(&id) {
+            Ok(technique) => technique,
+            Err(e) => {
+                error!("Skipping {}: {}", &id, e);
+                continue;
+            }
+        };
+
+        for (test_num, test) in technique.atomic_tests.iter().enumerate() {
+            if !filter.matches(&technique, test) {
+                continue;
+            }
+
+            tests.push(TestSummary {
+                technique: technique.attack_technique.clone(),
+                display_name: technique.display_name.clone(),
+                test_number: test_num + 1,
+                test_name: test.name.clone(),
+                guid: test.auto_generated_guid.clone(),
+                platforms: test.supported_platforms.clone(),
+                executor: test.executor.name.clone(),
+                elevation_required: elevation_required(test),
+            });
+        }
+    }
+
+    tests.sort_by(|a, b| (&a.technique, a.test_number).cmp(&(&b.technique, b.test_number)));
+
+    Ok(tests)
+}
+
+fn elevation_required(test: &AtomicTest) -> bool {
+    test.executor.elevation_required.unwrap_or(false)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn list(filter: TestFilter) -> Vec<(String, usize)> {
+        list_tests(&[PathBuf::from("atomic-red-team-master")], None, &filter)
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.technique, t.test_number))
+            .collect()
+    }
+
+    #[test]
+    fn filters() {
+        let t1082 = ("T1082".to_string(), 1);
+        let both = vec![("T1574.006".to_string(), 1), ("T1574.006".to_string(), 2)];
+
+        assert_eq!(
+            list(TestFilter::default()),
+            [vec![t1082.clone()], both.clone()].concat()
+        );
+        assert_eq!(
+            list(TestFilter {
+                platform: Some("Linux".to_string()),
+                executor: Some("BASH".to_string()),
+                ..Default::default()
+            }),
+            both
+        );
+        assert_eq!(
+            list(TestFilter {
+                platform: Some("windows".to_string()),
+                ..Default::default()
+            }),
+            vec![]
+        );
+        assert_eq!(
+            list(TestFilter {
+                elevation_required: Some(false),
+                ..Default::default()
+            }),
+            vec![t1082, ("T1574.006".to_string(), 2)]
+        );
+        assert_eq!(
+            list(TestFilter {
+                search: Some("ENVIRONMENT variable".to_string()),
+                ..Default::default()
+            }),
+            vec![("T1574.006".to_string(), 2)]
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use std::io::{self, BufRead, IsTerminal, Write};
+
+use log::{error, info};
+use serde::Serialize;
+
+use crate::error::ArrError;
+
+/// What the operator reported after working through a manual test's steps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ManualOutcome {
+    Completed,
+    Skipped,
+    Failed,
+}
+
+/// Show the steps to the operator on the terminal and wait for their answer.
+///
+/// Without a terminal there is nobody to perform the steps, so they are skipped.
+pub fn perform_steps(steps: &str) -> Result<ManualOutcome, ArrError> {
+    let stdin = io::stdin();
+
+    if !stdin.is_terminal() {
+        error!("Manual steps need an operator at a terminal, skipping them");
+        return Ok(ManualOutcome::Skipped);
+    }
+
+    confirm_steps(steps, &mut stdin.lock(), &mut io::stdout().lock())
+}
+
+/// Show the steps and ask whether they were completed, skipped or failed.
+pub fn confirm_steps<R: BufRead, W: Write>(
+    steps: &str,
+    input: &mut R,
+    output: &mut W,
+) -> Result<ManualOutcome, ArrError> {
+    let io_err = |e: io::Error| ArrError::CommandIoFailure(e.to_string());
+
+    writeln!(output, "Perform the following steps:\n").map_err(io_err)?;
+    for line in steps.trim_end().lines() {
+        writeln!(output, "    {}", line).map_err(io_err)?;
+    }
+    writeln!(output).map_err(io_err)?;
+
+    loop {
+        write!(output, "[c]ompleted, [s]kip or [f]ailed? ").map_err(io_err)?;
+        output.flush().map_err(io_err)?;
+
+        let mut answer = String::new();
+        if input.read_line(&mut answer).map_err(io_err)? == 0 {
+            error!("No answer from the operator");
+            return Err(ArrError::CommandIoFailure(
+                "No answer from the operator".to_string(),
+            ));
+        }
+
+        let outcome = match answer.trim().to_lowercase().as_str() {
+            "c" | "completed" | "complete" => ManualOutcome::Completed,
+            "s" | "skip" | "skipped" => ManualOutcome::Skipped,
+            "f" | "fail" | "failed" => ManualOutcome::Failed,
+            _ => continue,
+        };
+
+        info!("The operator reported the steps as {:?}", outcome);
+        return Ok(outcome);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    fn confirm(answers: &str) -> (Result<ManualOutcome, ArrError>, String) {
+        let mut input = Cursor::new(answers.as_bytes());
+        let mut output = Vec::new();
+
+        let res = confirm_steps("1. Open regedit\n2. Look around\n", &mut input, &mut output);
+
+        (res, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn renders_steps() {
+        let (res, output) = confirm("c\n");
+
+        assert_eq!(res, Ok(ManualOutcome::Completed));
+        assert!(output.contains("    1. Open regedit\n    2. Look around\n"));
+    }
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(confirm("skip\n").0, Ok(ManualOutcome::Skipped));
+        assert_eq!(confirm("F\n").0, Ok(ManualOutcome::Failed));
+    }
+
+    #[test]
+    fn asks_again_until_understood() {
+        let (res, output) = confirm("maybe\n\nc\n");
+
+        assert_eq!(res, Ok(ManualOutcome::Completed));
+        assert_eq!(output.matches("[c]ompleted").count(), 3);
+    }
+
+    #[test]
+    fn no_answer() {
+        assert!(confirm("").0.is_err());
+    }
+}
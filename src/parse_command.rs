@@ -1,89 +1,203 @@
 use nom::{
     branch::alt,
     bytes::complete::{is_not, tag, take},
-    combinator::{cut, fail, map, map_parser},
-    multi::many0,
+    combinator::{cut, fail, map, map_parser, recognize},
+    multi::{fold_many0, many0},
     sequence::delimited,
     IResult,
 };
 
-use std::{collections::HashMap, path::Path};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use crate::{builtin::Builtins, error::ArrError, quote::Quoting, values::ValueSource};
 
-use crate::error::ArrError;
+/// A piece of a command, either as written or a variable and its value.
+enum Piece<'i> {
+    Text(&'i str),
+    Var(&'i str, &'i str),
+}
 
 fn lookup_value<'i>(
     args: &'i HashMap<String, String>,
-) -> impl FnMut(&'i str) -> IResult<&'i str, String> {
-    move |input| match args.get(&input.to_string()) {
-        Some(value) => Ok(("", value.clone())),
+) -> impl FnMut(&'i str) -> IResult<&'i str, Piece<'i>> {
+    move |input| match args.get(input) {
+        Some(value) => Ok(("", Piece::Var(input, value))),
         None => cut(fail)(input),
     }
 }
 
-fn get_vars<'i>(
+/// Substitute each variable's value, as `quote` writes it given the command before it.
+fn get_vars<'i, Q>(
     args: &'i HashMap<String, String>,
-) -> impl FnMut(&'i str) -> IResult<&'i str, String> {
+    quote: Q,
+) -> impl FnMut(&'i str) -> IResult<&'i str, String>
+where
+    Q: Fn(&str, &str, &str) -> String,
+{
     move |input| {
-        let (tail, head) = many0(alt((
-            // get anything before #
-            map(is_not("#"), String::from),
-            // get a variable and replace it with its value
-            // variables are surrounded with "#{" and "}"
-            map_parser(
-                delimited(tag("#{"), is_not("}"), tag("}")),
-                lookup_value(args),
-            ),
-            // if the '#' was a false alarm, eat it up
-            map(take(1usize), String::from),
-        )))(input)?;
+        fold_many0(
+            alt((
+                // get anything before #
+                map(is_not("#"), Piece::Text),
+                // get a variable and replace it with its value
+                // variables are surrounded with "#{" and "}"
+                map_parser(
+                    delimited(tag("#{"), is_not("}"), tag("}")),
+                    lookup_value(args),
+                ),
+                // if the '#' was a false alarm, eat it up
+                map(take(1usize), Piece::Text),
+            )),
+            String::new,
+            |mut command, piece| {
+                match piece {
+                    Piece::Text(text) => command.push_str(text),
+                    Piece::Var(name, value) => {
+                        let value = quote(&command, name, value);
+                        command.push_str(&value);
+                    }
+                }
+                command
+            },
+        )(input)
+    }
+}
 
-        let head = head.join("");
+/// The values for a test's variables, and which of them the user set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Variables {
+    pub values: HashMap<String, String>,
+    /// Given by the user, so escaped for the shell they are substituted into
+    pub set: HashSet<String>,
+    /// The defaults that use the user's values, as written with those values still to substitute
+    pub derived: HashMap<String, String>,
+    /// Where each input argument's value came from
+    pub sources: HashMap<String, ValueSource>,
+    /// Substitute the user's values as written
+    pub raw: bool,
+    pub builtins: Builtins,
+}
 
-        Ok((tail, head))
+impl From<HashMap<String, String>> for Variables {
+    fn from(values: HashMap<String, String>) -> Self {
+        Self {
+            values,
+            ..Default::default()
+        }
     }
 }
 
-pub fn update_path(atomics_dir: &str) -> impl FnMut(&str) -> IResult<&str, String> + '_ {
-    move |input| {
-        let (tail, head) = many0(alt((
-            map(is_not("P"), String::from),
-            map(tag("PathToAtomicsFolder"), |_| atomics_dir.to_string()),
-            map(take(1usize), String::from),
-        )))(input)?;
+impl Variables {
+    fn quoted(&self, name: &str) -> bool {
+        !self.raw && self.set.contains(name)
+    }
+}
 
-        let head = head.join("");
+/// The name of every `#{variable}` in a command, in order.
+pub(crate) fn variables(input: &str) -> Vec<&str> {
+    let res: IResult<&str, Vec<Option<&str>>> = many0(alt((
+        map(is_not("#"), |_| None),
+        map(delimited(tag("#{"), is_not("}"), tag("}")), Some),
+        map(take(1usize), |_| None),
+    )))(input);
+
+    match res {
+        Ok((_tail, names)) => names.into_iter().flatten().collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Substitute the variables that have values, leaving the rest as written.
+pub(crate) fn fill(input: &str, values: &HashMap<String, String>) -> String {
+    let res: IResult<&str, String> = fold_many0(
+        alt((
+            is_not("#"),
+            map(
+                recognize(delimited(tag("#{"), is_not("}"), tag("}"))),
+                |var: &str| {
+                    values
+                        .get(&var[2..var.len() - 1])
+                        .map_or(var, String::as_str)
+                },
+            ),
+            take(1usize),
+        )),
+        String::new,
+        |mut filled, piece| {
+            filled.push_str(piece);
+            filled
+        },
+    )(input);
 
-        Ok((tail, head))
+    match res {
+        Ok((_tail, filled)) => filled,
+        Err(_) => input.to_string(),
     }
 }
 
+/// Each `#{variable}` in a command, with the one-based line and column of its `#`.
+pub(crate) fn placeholders(input: &str) -> Vec<(usize, usize, &str)> {
+    variables(input)
+        .into_iter()
+        .map(|name| {
+            // the name borrows from the input, just after its `#{`
+            let offset = name.as_ptr() as usize - input.as_ptr() as usize - 2;
+            let before = &input[..offset];
+            let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+
+            (
+                before.matches('\n').count() + 1,
+                before[line_start..].chars().count() + 1,
+                name,
+            )
+        })
+        .collect()
+}
+
 pub fn parse_command(
     command: &str,
-    atomics_dir: &Path,
-    args: &HashMap<String, String>,
+    vars: &Variables,
+    quoting: Option<Quoting>,
 ) -> Result<String, ArrError> {
-    let path = atomics_dir.to_str().unwrap_or("");
+    // a default using the user's values is written out, so that only their values are quoted
+    let parsed_command = fill(&vars.builtins.substitute(command), &vars.derived);
 
-    log::info!("Path: {}", &path);
+    log::info!("Built-ins substituted: {}", &parsed_command);
 
-    let (_tail, parsed_command) =
-        update_path(path)(command).map_err(|e| ArrError::OtherNomError(e.to_string()))?;
+    // some values can't be made safe where they are written, whatever the quoting
+    let unquotable: RefCell<Option<String>> = RefCell::new(None);
 
-    log::info!("Path: {}", &parsed_command);
+    let quote = |before: &str, name: &str, value: &str| match quoting {
+        Some(quoting) if vars.quoted(name) => {
+            if !quoting.can_quote(before, value) {
+                unquotable
+                    .borrow_mut()
+                    .get_or_insert_with(|| name.to_string());
+            }
+            quoting.quote(before, value)
+        }
+        _ => value.to_string(),
+    };
 
-    let res = match get_vars(args)(&parsed_command) {
+    let res = match get_vars(&vars.values, quote)(&parsed_command) {
         Ok((_tail, c)) => Ok(c),
         Err(nom::Err::Failure(e)) => Err(ArrError::ArgValueNotFound(e.input.to_string())),
         Err(e) => Err(ArrError::OtherNomError(e.to_string())),
     };
 
-    res
+    match unquotable.into_inner() {
+        Some(name) => Err(ArrError::CannotQuote(name)),
+        None => res,
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    // use std::path::Path;
+    use std::path::Path;
 
     fn setup_args() -> HashMap<String, String> {
         let mut args = HashMap::new();
@@ -94,10 +208,14 @@ mod test {
         args
     }
 
+    fn raw(_before: &str, _name: &str, value: &str) -> String {
+        value.to_string()
+    }
+
     #[test]
     fn test_parse_command() {
         assert_eq!(
-            parse_command("abc_#{var1}_b_c", Path::new("art"), &setup_args()),
+            parse_command("abc_#{var1}_b_c", &setup_args().into(), None),
             Ok("abc_1_b_c".to_string()),
         );
     }
@@ -105,7 +223,7 @@ mod test {
     #[test]
     fn test_parse_command_failure() {
         assert_eq!(
-            parse_command("abc_#{var9}_b_c", Path::new("art"), &setup_args()),
+            parse_command("abc_#{var9}_b_c", &setup_args().into(), None),
             Err(ArrError::ArgValueNotFound("var9".to_string()))
         );
     }
@@ -113,7 +231,7 @@ mod test {
     #[test]
     fn no_vars() {
         assert_eq!(
-            get_vars(&setup_args())("abc_123_b_c"),
+            get_vars(&setup_args(), raw)("abc_123_b_c"),
             Ok(("", "abc_123_b_c".to_string())),
         );
     }
@@ -121,7 +239,7 @@ mod test {
     #[test]
     fn var_in_middle() {
         assert_eq!(
-            get_vars(&setup_args())("abc_#{var1}_b_c"),
+            get_vars(&setup_args(), raw)("abc_#{var1}_b_c"),
             Ok(("", "abc_1_b_c".to_string())),
         );
     }
@@ -129,7 +247,7 @@ mod test {
     #[test]
     fn var_at_beginning() {
         assert_eq!(
-            get_vars(&setup_args())("#{var1}_b_c"),
+            get_vars(&setup_args(), raw)("#{var1}_b_c"),
             Ok(("", "1_b_c".to_string())),
         );
     }
@@ -137,7 +255,7 @@ mod test {
     #[test]
     fn just_var() {
         assert_eq!(
-            get_vars(&setup_args())("#{var1}"),
+            get_vars(&setup_args(), raw)("#{var1}"),
             Ok(("", "1".to_string()))
         );
     }
@@ -145,7 +263,7 @@ mod test {
     #[test]
     fn fake_out_var() {
         assert_eq!(
-            get_vars(&setup_args())("#{var1"),
+            get_vars(&setup_args(), raw)("#{var1"),
             Ok(("", "#{var1".to_string())),
         );
     }
@@ -153,7 +271,7 @@ mod test {
     #[test]
     fn tricky_vars() {
         assert_eq!(
-            get_vars(&setup_args())("abc_##{var1}#_#{var2}_#{var3}"),
+            get_vars(&setup_args(), raw)("abc_##{var1}#_#{var2}_#{var3}"),
             Ok(("", "abc_#1#_2_3".to_string())),
         );
     }
@@ -161,7 +279,7 @@ mod test {
     #[test]
     fn multiple_vars() {
         assert_eq!(
-            get_vars(&setup_args())("abc_#{var1}_#{var2}_#{var3}"),
+            get_vars(&setup_args(), raw)("abc_#{var1}_#{var2}_#{var3}"),
             Ok(("", "abc_1_2_3".to_string())),
         );
     }
@@ -169,7 +287,7 @@ mod test {
     #[test]
     fn value_not_found() {
         assert_eq!(
-            get_vars(&setup_args())("abc_#{var1}_#{var9}_#{var3}"),
+            get_vars(&setup_args(), raw)("abc_#{var1}_#{var9}_#{var3}"),
             Err(nom::Err::Failure(nom::error::Error {
                 input: "var9",
                 code: nom::error::ErrorKind::Fail,
@@ -178,10 +296,86 @@ mod test {
     }
 
     #[test]
-    fn test_update_path() {
+    fn test_builtins() {
+        let vars = Variables {
+            builtins: Builtins::new(Path::new("yolo"), Path::new("yolo/T1")),
+            ..setup_args().into()
+        };
+        let sep = std::path::MAIN_SEPARATOR;
+
+        assert_eq!(
+            parse_command("___ PathToAtomicsFolder/LOL/#{var1}", &vars, None),
+            Ok(format!("___ yolo{0}LOL{0}1", sep))
+        );
+    }
+
+    #[test]
+    fn test_variables() {
+        assert_eq!(
+            variables("a # b #{var1} #{var2}#{var1} #{unclosed"),
+            vec!["var1", "var2", "var1"]
+        );
+    }
+
+    #[test]
+    fn test_placeholders() {
+        assert_eq!(
+            placeholders("#{a}\n  é #{b} ##{c}\n#{d"),
+            vec![(1, 1, "a"), (2, 5, "b"), (2, 11, "c")]
+        );
+    }
+
+    #[test]
+    fn test_fill() {
+        assert_eq!(
+            fill("#{var1}/#{var9}/##{var2}#", &setup_args()),
+            "1/#{var9}/#2#"
+        );
+    }
+
+    #[test]
+    fn set_values_are_quoted() {
+        let mut vars = Variables::from(setup_args());
+        vars.values
+            .insert("file".to_string(), "a b; rm -rf ~".to_string());
+        vars.values
+            .insert("lines".to_string(), "a\r\nb".to_string());
+        vars.set = ["file", "lines", "var1"].map(String::from).into();
+
+        let parse = |command: &str, vars: &Variables, quoting: Option<Quoting>| {
+            parse_command(command, vars, quoting)
+        };
+
+        assert_eq!(
+            parse("cat #{file} #{var1} #{var2}", &vars, Some(Quoting::Posix)),
+            Ok("cat 'a b; rm -rf ~' 1 2".to_string())
+        );
+        assert_eq!(
+            parse("cat \"#{file}\"", &vars, Some(Quoting::PowerShell)),
+            Ok("cat \"a b; rm -rf ~\"".to_string())
+        );
+        assert_eq!(
+            parse("type #{lines}", &vars, Some(Quoting::Cmd)),
+            Err(ArrError::CannotQuote("lines".to_string()))
+        );
+        assert_eq!(
+            parse("cat #{lines}", &vars, Some(Quoting::Posix)),
+            Ok("cat 'a\r\nb'".to_string())
+        );
+        assert_eq!(
+            parse("id # note #{lines}", &vars, Some(Quoting::Posix)),
+            Err(ArrError::CannotQuote("lines".to_string()))
+        );
+        // manual steps are read, not run
+        assert_eq!(
+            parse("Open #{file}", &vars, None),
+            Ok("Open a b; rm -rf ~".to_string())
+        );
+
+        vars.raw = true;
         assert_eq!(
-            update_path("yolo")("___PathToAtomicsFolder/LOL/123"),
-            Ok(("", "___yolo/LOL/123".to_string()))
+            parse("cat #{file}", &vars, Some(Quoting::Posix)),
+            Ok("cat a b; rm -rf ~".to_string())
         );
     }
 }
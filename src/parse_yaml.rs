@@ -1,9 +1,15 @@
-use std::{collections::HashMap, fs::File, path::Path};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 use log::{error, info};
 use serde::Deserialize;
 
-use crate::error::ArrError;
+use crate::{
+    argument::ArgType,
+    error::{ArrError, SourceError},
+};
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
 pub struct AtomicReadTeamTechnique {
@@ -12,6 +18,35 @@ pub struct AtomicReadTeamTechnique {
     pub atomic_tests: Vec<AtomicTest>,
 }
 
+impl AtomicReadTeamTechnique {
+    /// The test at a zero-based position, if the technique has one there.
+    pub fn test(&self, test_num: usize) -> Result<&AtomicTest, ArrError> {
+        self.atomic_tests.get(test_num).ok_or_else(|| {
+            error!(
+                "{} has no test {}, it has {}",
+                &self.attack_technique,
+                test_num + 1,
+                self.atomic_tests.len()
+            );
+            self.test_not_found(&(test_num + 1).to_string())
+        })
+    }
+
+    /// A `TestNotFound` listing every test the technique does have.
+    pub fn test_not_found(&self, requested: &str) -> ArrError {
+        ArrError::TestNotFound {
+            technique: self.attack_technique.clone(),
+            requested: requested.to_string(),
+            available: self
+                .atomic_tests
+                .iter()
+                .enumerate()
+                .map(|(i, t)| format!("{}: {}", i + 1, t.name))
+                .collect(),
+        }
+    }
+}
+
 #[derive(Deserialize, PartialEq, Eq, Debug)]
 pub struct AtomicTest {
     pub name: String,
@@ -23,6 +58,12 @@ pub struct AtomicTest {
     pub input_arguments: HashMap<String, AtomicInputArg>,
     pub dependency_executor_name: Option<String>,
     pub dependencies: Option<Vec<AtomicDependency>>,
+    /// The atomics directory the test was loaded from, which `PathToAtomicsFolder` points at
+    #[serde(skip)]
+    pub atomics_dir: PathBuf,
+    /// The technique's own directory, which `PathToTechniqueFolder` points at
+    #[serde(skip)]
+    pub technique_dir: PathBuf,
 }
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
@@ -46,14 +87,15 @@ pub struct AtomicInputArg {
     pub description: String,
     pub default: String,
     #[serde(rename = "type")]
-    pub arg_type: String,
+    pub arg_type: ArgType,
 }
 
 pub fn parse_art_file(art_technique_file: &Path) -> Result<AtomicReadTeamTechnique, ArrError> {
-    let f = std::fs::File::open(art_technique_file)
-        .map_err(|_| ArrError::FileNotFound(art_technique_file.to_string_lossy().to_string()))?;
+    let text = std::fs::read_to_string(art_technique_file).map_err(|e| {
+        ArrError::CannotReadFile(SourceError::unreadable(art_technique_file.to_owned(), &e))
+    })?;
 
-    match serde_yaml::from_reader::<File, AtomicReadTeamTechnique>(f) {
+    match serde_yaml::from_str::<AtomicReadTeamTechnique>(&text) {
         Ok(y) => {
             info!(
                 "Successfully parsed: {}",
@@ -63,7 +105,11 @@ pub fn parse_art_file(art_technique_file: &Path) -> Result<AtomicReadTeamTechniq
         }
         Err(e) => {
             error!("Failed to parse: {}", &art_technique_file.to_string_lossy());
-            Err(ArrError::CannotParseYaml(e.to_string()))
+            Err(ArrError::CannotParseYaml(SourceError::from_yaml(
+                art_technique_file.to_owned(),
+                &text,
+                &e,
+            )))
         }
     }
 }
@@ -74,6 +120,61 @@ mod tests {
 
     #[test]
     fn read_file() {
-        assert!(parse_art_file(Path::new("T1574.006.yaml")).is_ok())
+        assert!(parse_art_file(Path::new(
+            "atomic-red-team-master/atomics/T1574.006/T1574.006.yaml"
+        ))
+        .is_ok())
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        let yaml = parse_art_file(Path::new(
+            "atomic-red-team-master/atomics/T1574.006/T1574.006.yaml",
+        ))
+        .unwrap();
+
+        assert!(yaml.test(1).is_ok());
+        assert!(matches!(
+            yaml.test(41),
+            Err(ArrError::TestNotFound { requested, available, .. })
+                if requested == "42" && available.len() == yaml.atomic_tests.len()
+        ));
+    }
+
+    #[test]
+    fn parse_errors_are_located() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("T0000.019.yaml");
+        std::fs::write(
+            &path,
+            "attack_technique: T0000.019\ndisplay_name: Broken\natomic_tests: 3\n",
+        )
+        .unwrap();
+
+        let e = match parse_art_file(&path) {
+            Err(ArrError::CannotParseYaml(e)) => e,
+            other => panic!("expected a parse error, got {:?}", other),
+        };
+
+        assert_eq!(e.line, Some(3));
+        assert_eq!(e.column, Some(15));
+        assert_eq!(e.snippet.as_deref(), Some("atomic_tests: 3"));
+        assert_eq!(
+            e.diagnostic(),
+            format!(
+                "error: atomic_tests: invalid type: integer `3`, expected a sequence
+ --> {}:3:15
+  |
+3 | atomic_tests: 3
+  |               ^",
+                path.display()
+            )
+        );
+
+        let missing = dir.path().join("T0000.020.yaml");
+        assert!(matches!(
+            parse_art_file(&missing),
+            Err(ArrError::CannotReadFile(SourceError { path, line: None, .. })) if path == missing
+        ));
     }
 }
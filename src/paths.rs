@@ -0,0 +1,34 @@
+use std::{env, path::PathBuf};
+
+/// Where arr keeps state that must survive between runs.
+///
+/// `$XDG_STATE_HOME/arr` or `~/.local/state/arr` on Unix, `%LOCALAPPDATA%\arr` on Windows.
+pub fn state_dir() -> PathBuf {
+    if cfg!(windows) {
+        return local_app_data().join("arr");
+    }
+
+    match env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("arr"),
+        None => home().join(".local").join("state").join("arr"),
+    }
+}
+
+/// `$XDG_STATE_HOME/arr/index`, or the platform's equivalent, where technique indexes are cached.
+pub fn index_dir() -> PathBuf {
+    state_dir().join("index")
+}
+
+fn home() -> PathBuf {
+    env::var_os("HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
+
+fn local_app_data() -> PathBuf {
+    env::var_os("LOCALAPPDATA")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(env::temp_dir)
+}
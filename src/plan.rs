@@ -0,0 +1,118 @@
+use std::{collections::BTreeMap, fmt};
+
+use serde::Serialize;
+
+use crate::report::Phase;
+
+/// A dependency's check command paired with the command that gets it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Prerequisite {
+    pub description: String,
+    pub executor: String,
+    /// How the executor is run, with the script shown as `<script>`
+    pub program: Option<String>,
+    pub check_command: String,
+    pub get_command: String,
+}
+
+/// A command that would run, after every substitution.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedCommand {
+    pub phase: Phase,
+    pub executor: String,
+    /// How the executor is run, with the script shown as `<script>`
+    pub program: Option<String>,
+    pub command: String,
+}
+
+/// Everything a test would do, worked out without running anything.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExecutionPlan {
+    pub technique: String,
+    pub test_number: usize,
+    pub test_name: String,
+    pub guid: String,
+    /// Running the test needs root, which planning it does not
+    pub elevation_required: bool,
+    pub variables: BTreeMap<String, String>,
+    /// Each get command only runs when its check fails
+    pub prerequisites: Vec<Prerequisite>,
+    pub attack: PlannedCommand,
+    pub cleanup: Option<PlannedCommand>,
+}
+
+fn write_command(
+    f: &mut fmt::Formatter,
+    executor: &str,
+    program: Option<&str>,
+    command: &str,
+) -> fmt::Result {
+    writeln!(f, "    executor: {}", executor)?;
+    if let Some(program) = program {
+        writeln!(f, "    program: {}", program)?;
+    }
+    for line in command.trim_end().lines() {
+        writeln!(f, "    | {}", line)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ExecutionPlan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{} Test {}: {} ({})",
+            self.technique, self.test_number, self.test_name, self.guid
+        )?;
+        if self.elevation_required {
+            writeln!(f, "Requires elevation to run")?;
+        }
+
+        writeln!(f, "\nVariables:")?;
+        if self.variables.is_empty() {
+            writeln!(f, "    (None)")?;
+        }
+        for (name, value) in &self.variables {
+            writeln!(f, "    {} = {}", name, value)?;
+        }
+
+        for (i, prerequisite) in self.prerequisites.iter().enumerate() {
+            writeln!(f, "\nPrerequisite {}: {}", i + 1, prerequisite.description)?;
+            writeln!(f, "  check:")?;
+            let program = prerequisite.program.as_deref();
+            write_command(
+                f,
+                &prerequisite.executor,
+                program,
+                &prerequisite.check_command,
+            )?;
+            writeln!(f, "  get, if the check fails:")?;
+            write_command(
+                f,
+                &prerequisite.executor,
+                program,
+                &prerequisite.get_command,
+            )?;
+        }
+
+        writeln!(f, "\nAttack:")?;
+        write_command(
+            f,
+            &self.attack.executor,
+            self.attack.program.as_deref(),
+            &self.attack.command,
+        )?;
+
+        if let Some(cleanup) = &self.cleanup {
+            writeln!(f, "\nCleanup:")?;
+            write_command(
+                f,
+                &cleanup.executor,
+                cleanup.program.as_deref(),
+                &cleanup.command,
+            )?;
+        }
+
+        Ok(())
+    }
+}
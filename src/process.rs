@@ -0,0 +1,404 @@
+use std::{
+    io::{Read, Write},
+    process::{Child, Command, Output, Stdio},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{error, info};
+use tempfile::TempPath;
+
+use crate::error::ArrError;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+// the number of times SIGINT has been received, once `watch_interrupts` is called
+// or while a command runs in its own process group
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+static WATCHING: AtomicBool = AtomicBool::new(false);
+// the commands running in their own process group
+static DETACHED: Mutex<usize> = Mutex::new(0);
+
+/// Catch SIGINT instead of dying, so running commands can be killed and cleaned up.
+///
+/// Once watching, a command that is running when SIGINT arrives is killed and
+/// returns `ArrError::Interrupted`. Commands started after that are unaffected
+/// until the next SIGINT. Between commands, `check_interrupted` stops the test.
+#[cfg(unix)]
+pub fn watch_interrupts() -> Result<(), ArrError> {
+    set_interrupt_handler(true)?;
+
+    WATCHING.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn watch_interrupts() -> Result<(), ArrError> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn set_interrupt_handler(watch: bool) -> Result<(), ArrError> {
+    use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+    extern "C" fn on_interrupt(_: nix::libc::c_int) {
+        INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let handler = match watch {
+        true => SigHandler::Handler(on_interrupt),
+        false => SigHandler::SigDfl,
+    };
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+
+    // SAFETY: the handler only touches an atomic
+    unsafe { sigaction(Signal::SIGINT, &action) }
+        .map(|_| ())
+        .map_err(|e| ArrError::Other(format!("Cannot catch SIGINT: {}", e)))
+}
+
+/// Catches SIGINT while a command runs in its own process group, where SIGINT from the terminal
+/// no longer reaches it, so that it is killed along with arr instead of left running.
+#[cfg(unix)]
+struct Detached;
+
+#[cfg(unix)]
+impl Detached {
+    fn new() -> Result<Self, ArrError> {
+        let mut detached = DETACHED.lock().unwrap_or_else(|e| e.into_inner());
+        if *detached == 0 && !WATCHING.load(Ordering::SeqCst) {
+            set_interrupt_handler(true)?;
+        }
+        *detached += 1;
+
+        Ok(Self)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Detached {
+    fn drop(&mut self) {
+        let mut detached = DETACHED.lock().unwrap_or_else(|e| e.into_inner());
+        *detached -= 1;
+        if *detached == 0 && !WATCHING.load(Ordering::SeqCst) {
+            if let Err(e) = set_interrupt_handler(false) {
+                error!("{}", e);
+            }
+        }
+    }
+}
+
+/// Let SIGINT stop arr while `f` runs, since there is no command to kill while it blocks,
+/// e.g. waiting on the operator.
+#[cfg(unix)]
+pub(crate) fn with_default_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    if !WATCHING.load(Ordering::SeqCst) {
+        return f();
+    }
+
+    if let Err(e) = set_interrupt_handler(false) {
+        error!("{}", e);
+    }
+    let res = f();
+    if let Err(e) = set_interrupt_handler(true) {
+        error!("{}", e);
+    }
+
+    res
+}
+
+#[cfg(not(unix))]
+pub(crate) fn with_default_interrupts<T>(f: impl FnOnce() -> T) -> T {
+    f()
+}
+
+/// Stop before starting another command once SIGINT has been received.
+pub(crate) fn check_interrupted() -> Result<(), ArrError> {
+    match interrupted() {
+        true => Err(ArrError::Interrupted),
+        false => Ok(()),
+    }
+}
+
+/// Whether SIGINT has been received since `watch_interrupts` was called, or while a command
+/// ran in its own process group.
+pub fn interrupted() -> bool {
+    INTERRUPTS.load(Ordering::SeqCst) > 0
+}
+
+/// Run the command to completion, like `Command::output`, but give up after
+/// `timeout` or when SIGINT arrives.
+///
+/// When a timeout is set, or interrupts are watched, the child is placed in its
+/// own process group, so that everything it spawned can be killed along with it.
+/// That includes anything it left in the background still holding its output
+/// open when the timeout is reached. SIGINT is caught until it finishes, even
+/// without `watch_interrupts`, since it would no longer reach the child.
+pub fn output_with_timeout(
+    command: &mut Command,
+    timeout: Option<Duration>,
+) -> Result<Output, ArrError> {
+    if timeout.is_none() && !WATCHING.load(Ordering::SeqCst) {
+        return command
+            .output()
+            .map_err(|e| ArrError::CommandIoFailure(e.to_string()));
+    }
+
+    #[cfg(unix)]
+    let _detached = {
+        use std::os::unix::process::CommandExt;
+        command.process_group(0);
+        Detached::new()?
+    };
+
+    let interrupts = INTERRUPTS.load(Ordering::SeqCst);
+
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| ArrError::CommandIoFailure(e.to_string()))?;
+
+    // drain the pipes while we wait, otherwise a chatty child blocks on a full pipe
+    let stdout = Reader::spawn(child.stdout.take());
+    let stderr = Reader::spawn(child.stderr.take());
+
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                let timeout = timeout.unwrap_or_default();
+                error!("Command did not finish within {:?}, killing it", &timeout);
+                kill_process_tree(&mut child);
+                return Err(ArrError::Timeout(timeout));
+            }
+            Ok(None) if INTERRUPTS.load(Ordering::SeqCst) != interrupts => {
+                error!("Interrupted, killing the running command");
+                kill_process_tree(&mut child);
+                return Err(ArrError::Interrupted);
+            }
+            Ok(None) => thread::sleep(POLL_INTERVAL),
+            Err(e) => return Err(ArrError::CommandIoFailure(e.to_string())),
+        }
+    };
+
+    // whatever the command left in the background may still hold the pipes open,
+    // so the output only gets what is left of the timeout
+    while !(stdout.is_finished() && stderr.is_finished()) {
+        if deadline.is_some_and(|d| Instant::now() >= d) {
+            error!("Command left processes running past the timeout, killing them");
+            kill_group(child.id());
+            break;
+        }
+        if INTERRUPTS.load(Ordering::SeqCst) != interrupts {
+            error!("Interrupted, killing the processes the command left running");
+            kill_group(child.id());
+            return Err(ArrError::Interrupted);
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    Ok(Output {
+        status,
+        stdout: stdout.take(),
+        stderr: stderr.take(),
+    })
+}
+
+/// Write a script to a temporary file with the given extension.
+///
+/// The file is deleted when the returned path is dropped.
+pub fn write_script(script: &str, extension: &str) -> Result<TempPath, ArrError> {
+    let mut file = tempfile::Builder::new()
+        .prefix("arr-")
+        .suffix(&format!(".{}", extension))
+        .tempfile()
+        .map_err(|e| ArrError::CommandIoFailure(e.to_string()))?;
+
+    file.write_all(script.as_bytes())
+        .map_err(|e| ArrError::CommandIoFailure(e.to_string()))?;
+
+    // close the file so the interpreter can open it, on Windows especially
+    Ok(file.into_temp_path())
+}
+
+/// Reads a pipe to the end in the background, keeping what it has read so far.
+struct Reader {
+    handle: thread::JoinHandle<()>,
+    buf: Arc<Mutex<Vec<u8>>>,
+}
+
+impl Reader {
+    fn spawn<R: Read + Send + 'static>(pipe: Option<R>) -> Self {
+        let buf = Arc::new(Mutex::new(Vec::new()));
+        let shared = Arc::clone(&buf);
+
+        let handle = thread::spawn(move || {
+            let mut pipe = match pipe {
+                Some(pipe) => pipe,
+                None => return,
+            };
+            let mut chunk = [0; 8192];
+            while let Ok(n @ 1..) = pipe.read(&mut chunk) {
+                shared.lock().unwrap().extend_from_slice(&chunk[..n]);
+            }
+        });
+
+        Self { handle, buf }
+    }
+
+    fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// What has been read, without waiting for the pipe to close.
+    fn take(self) -> Vec<u8> {
+        let buf = std::mem::take(&mut *self.buf.lock().unwrap());
+        buf
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_tree(child: &mut Child) {
+    // the child leads its own process group, so its id is also the group id
+    kill_group(child.id());
+
+    let _ = child.wait();
+}
+
+#[cfg(unix)]
+fn kill_group(id: u32) {
+    use nix::{
+        sys::signal::{killpg, Signal},
+        unistd::Pid,
+    };
+
+    let pgid = Pid::from_raw(id as i32);
+
+    match killpg(pgid, Signal::SIGKILL) {
+        Ok(_) => info!("Killed process group {}", pgid),
+        Err(e) => error!("Failed to kill process group {}: {}", pgid, e),
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_tree(child: &mut Child) {
+    if let Err(e) = child.kill() {
+        error!("Failed to kill process {}: {}", child.id(), e);
+    }
+
+    let _ = child.wait();
+}
+
+// without process groups there is nothing left to kill once the child has exited
+#[cfg(not(unix))]
+fn kill_group(_: u32) {}
+
+#[cfg(all(test, unix))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn finishes_before_timeout() {
+        let output = output_with_timeout(
+            Command::new("sh").arg("-c").arg("echo hello"),
+            Some(Duration::from_secs(10)),
+        )
+        .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"hello\n");
+    }
+
+    #[test]
+    fn no_timeout() {
+        let output = output_with_timeout(Command::new("sh").arg("-c").arg("exit 3"), None).unwrap();
+
+        assert_eq!(output.status.code(), Some(3));
+    }
+
+    #[test]
+    fn kills_grandchildren() {
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+
+        // the grandchild would create the marker if it survived the timeout
+        let script = format!("(sleep 1; touch {}) & sleep 5", marker.to_string_lossy());
+
+        let start = Instant::now();
+        let res = output_with_timeout(
+            Command::new("sh").arg("-c").arg(script),
+            Some(Duration::from_millis(200)),
+        );
+
+        assert_eq!(res, Err(ArrError::Timeout(Duration::from_millis(200))));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn interrupts_reach_detached_commands() {
+        use nix::{
+            sys::signal::{kill, Signal},
+            unistd::getpid,
+        };
+
+        // SIGINT goes to the whole process, so the command is run by a copy of the tests
+        if let Ok(marker) = std::env::var("ARR_TEST_MARKER") {
+            thread::spawn(|| {
+                thread::sleep(Duration::from_millis(300));
+                kill(getpid(), Signal::SIGINT).unwrap();
+            });
+
+            let res = output_with_timeout(
+                Command::new("sh")
+                    .arg("-c")
+                    .arg(format!("sleep 1; touch {}", marker)),
+                Some(Duration::from_secs(10)),
+            );
+            assert_eq!(res, Err(ArrError::Interrupted));
+            return;
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let marker = dir.path().join("marker");
+
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "process::test::interrupts_reach_detached_commands",
+            ])
+            .env("ARR_TEST_MARKER", &marker)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        thread::sleep(Duration::from_millis(1500));
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn background_processes_cannot_outlive_the_timeout() {
+        // the background sleep keeps stdout open after the shell exits
+        let start = Instant::now();
+        let output = output_with_timeout(
+            Command::new("sh").arg("-c").arg("echo started; sleep 5 &"),
+            Some(Duration::from_millis(300)),
+        )
+        .unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"started\n");
+    }
+}
@@ -0,0 +1,700 @@
+/// The quoting rules of a shell, which values are escaped with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// `sh` and `bash`
+    Posix,
+    PowerShell,
+    /// `cmd`, running a batch file
+    Cmd,
+}
+
+/// Where in a script a value is being written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Context {
+    Unquoted,
+    Single,
+    Double,
+    /// A here-document or here-string, whether variables expand in it, and what ends it
+    Here {
+        expands: bool,
+        end: String,
+    },
+    Comment,
+    /// A PowerShell `<# ... #>` comment
+    BlockComment,
+}
+
+impl Quoting {
+    /// Escape a value so the shell reads it as one literal word, given the script before it.
+    pub fn quote(&self, before: &str, value: &str) -> String {
+        match self {
+            Quoting::Posix => quote_posix(before, value),
+            Quoting::PowerShell => quote_powershell(powershell_context(before), value),
+            Quoting::Cmd => quote_cmd(cmd_context(before), value),
+        }
+    }
+
+    /// Whether any escaping can keep the value from running as code, given the script before it.
+    ///
+    /// Comments and here-documents take their contents as written, so a value must not end them.
+    pub fn can_quote(&self, before: &str, value: &str) -> bool {
+        match self {
+            Quoting::Posix => posix_can_quote(before, value),
+            Quoting::PowerShell => match powershell_context(before) {
+                Context::Comment => !value.contains(['\r', '\n']),
+                Context::BlockComment => !value.contains("#>"),
+                Context::Here { end, .. } => !value
+                    .split(['\r', '\n'])
+                    .any(|line| line.trim_start().starts_with(&end)),
+                _ => true,
+            },
+            // every line of a batch file is a new command, whatever the quotes
+            Quoting::Cmd => !value.contains(['\r', '\n']),
+        }
+    }
+}
+
+fn posix_can_quote(before: &str, value: &str) -> bool {
+    let (context, opened, _) = posix_context(before);
+
+    // a quoted `sh -c` script must be safe for the shell that runs it
+    if let Some(opened) = opened {
+        if runs_script(&before[..opened]) {
+            return posix_can_quote(&before[opened + 1..], value);
+        }
+    }
+
+    match context {
+        Context::Comment => !value.contains('\n'),
+        // `<<-` strips leading tabs before looking for the delimiter
+        Context::Here { end, .. } => !value
+            .split('\n')
+            .any(|line| line.trim_start_matches('\t') == end),
+        _ => true,
+    }
+}
+
+fn quote_posix(before: &str, value: &str) -> String {
+    let (context, opened, backquoted) = posix_context(before);
+    let quoted = quote_posix_in(before, context, opened, value);
+
+    // the shell takes out a backslash before these when it reads a backquoted command
+    (0..backquoted).fold(quoted, |quoted, _| escape(&quoted, "\\$`", '\\'))
+}
+
+fn quote_posix_in(before: &str, context: Context, opened: Option<usize>, value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_@%+=:,./-".contains(c);
+
+    // a quoted `sh -c` script is run by another shell, so it is quoted for that one too
+    if let Some(opened) = opened {
+        if runs_script(&before[..opened]) {
+            let inner = quote_posix(&before[opened + 1..], value);
+
+            return match context {
+                Context::Single => inner.replace('\'', r"'\''"),
+                _ => escape(&inner, "\\\"$`", '\\'),
+            };
+        }
+    }
+
+    match context {
+        Context::Unquoted if !value.is_empty() && value.chars().all(safe) => value.to_string(),
+        Context::Unquoted => format!("'{}'", value.replace('\'', r"'\''")),
+        Context::Single => value.replace('\'', r"'\''"),
+        Context::Double => escape(value, "\\\"$`", '\\'),
+        Context::Here { expands: true, .. } => escape(value, "\\$`", '\\'),
+        Context::Here { expands: false, .. } | Context::Comment | Context::BlockComment => {
+            value.to_string()
+        }
+    }
+}
+
+// PowerShell treats the typographic quotes like the plain ones
+const PS_SINGLE: &str = "'\u{2018}\u{2019}\u{201a}\u{201b}";
+const PS_DOUBLE: &str = "\"\u{201c}\u{201d}\u{201e}";
+
+/// Whether the command before a quote is a shell that runs it, like `sudo sh -c` or `su root -c`.
+fn runs_script(command: &str) -> bool {
+    let mut words = command.split_whitespace().rev();
+    if words.next() != Some("-c") {
+        return false;
+    }
+
+    let mut words = words.skip_while(|w| w.starts_with('-'));
+    let program = |w: &str| w.rsplit('/').next().unwrap_or(w).to_string();
+
+    match (words.next().map(program), words.next().map(program)) {
+        (Some(shell), _)
+            if ["sh", "bash", "dash", "zsh", "ksh", "su"].contains(&shell.as_str()) =>
+        {
+            true
+        }
+        (_, Some(su)) => su == "su",
+        _ => false,
+    }
+}
+
+fn quote_powershell(context: Context, value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_./:\\".contains(c);
+    let double_single = || {
+        value
+            .chars()
+            .flat_map(|c| match PS_SINGLE.contains(c) {
+                true => vec![c, c],
+                false => vec![c],
+            })
+            .collect::<String>()
+    };
+
+    match context {
+        // a leading dash would be read as a parameter name
+        Context::Unquoted
+            if !value.is_empty() && !value.starts_with('-') && value.chars().all(safe) =>
+        {
+            value.to_string()
+        }
+        Context::Unquoted => format!("'{}'", double_single()),
+        Context::Single => double_single(),
+        Context::Double | Context::Here { expands: true, .. } => {
+            escape(value, &format!("`${}", PS_DOUBLE), '`')
+        }
+        Context::Here { expands: false, .. } | Context::Comment | Context::BlockComment => {
+            value.to_string()
+        }
+    }
+}
+
+fn quote_cmd(context: Context, value: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "_.:\\/-".contains(c);
+    // batch files expand `%` everywhere, `""` is a literal quote to most programs
+    let escaped = || value.replace('%', "%%").replace('"', "\"\"");
+
+    match context {
+        Context::Unquoted if !value.is_empty() && value.chars().all(safe) => value.to_string(),
+        Context::Unquoted => format!("\"{}\"", escaped()),
+        Context::Comment | Context::BlockComment => value.to_string(),
+        _ => escaped(),
+    }
+}
+
+fn escape(value: &str, special: &str, escape: char) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(c) {
+            escaped.push(escape);
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Whether a `#` at the position starts a comment, which it does at the start of a word.
+fn starts_word(chars: &[char], i: usize) -> bool {
+    i == 0 || chars[i - 1].is_whitespace() || ";&|(".contains(chars[i - 1])
+}
+
+/// Where a command substitution started, to go back to when it ends.
+struct Substitution {
+    context: Context,
+    opened: usize,
+    heredoc: Option<(String, bool)>,
+    /// The parentheses open inside it
+    depth: usize,
+    backquoted: bool,
+}
+
+/// The context at the end of the script, the byte offset of the quote it is inside of, and how
+/// many backquoted commands it is inside of.
+///
+/// A `$(...)` or `` `...` `` runs a new command wherever it is written, so its contents start
+/// out unquoted.
+fn posix_context(before: &str) -> (Context, Option<usize>, usize) {
+    let chars: Vec<char> = before.chars().collect();
+    let mut context = Context::Unquoted;
+    let mut opened = 0;
+    // here-documents start on the line after their `<<`
+    let mut pending: Vec<(String, bool, bool)> = Vec::new();
+    let mut heredoc: Option<(String, bool)> = None;
+    let mut substitutions: Vec<Substitution> = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+
+        if let (Some((delimiter, strip_tabs)), Context::Here { expands, .. }) = (&heredoc, &context)
+        {
+            if i == 0 || chars[i - 1] == '\n' {
+                // look at the whole line, the body ends at the delimiter
+                let end = chars[i..]
+                    .iter()
+                    .position(|&c| c == '\n')
+                    .map_or(chars.len(), |p| i + p);
+                let line: String = chars[i..end].iter().collect();
+                let line = match strip_tabs {
+                    true => line.trim_start_matches('\t'),
+                    false => line.as_str(),
+                };
+
+                if end < chars.len() && line == delimiter {
+                    // several here-documents on one line follow each other
+                    match pending.is_empty() {
+                        true => {
+                            heredoc = None;
+                            context = Context::Unquoted;
+                        }
+                        false => {
+                            let (delimiter, strip_tabs, expands) = pending.remove(0);
+                            context = Context::Here {
+                                expands,
+                                end: delimiter.clone(),
+                            };
+                            heredoc = Some((delimiter, strip_tabs));
+                        }
+                    }
+                    i = end + 1;
+                    continue;
+                }
+                // only a body that expands can run commands
+                if !expands {
+                    if end == chars.len() {
+                        break;
+                    }
+                    i = end + 1;
+                    continue;
+                }
+            }
+        }
+
+        let expands = matches!(
+            context,
+            Context::Unquoted | Context::Double | Context::Here { expands: true, .. }
+        );
+        let in_backquotes = substitutions.last().is_some_and(|s| s.backquoted);
+
+        match (&context, c) {
+            // a backquoted command ends at the next backquote, whatever the quotes
+            (_, '`') if in_backquotes => {
+                if let Some(s) = substitutions.pop() {
+                    context = s.context;
+                    opened = s.opened;
+                    heredoc = s.heredoc;
+                    depth = s.depth;
+                }
+            }
+            (_, '$' | '`') if expands && (c == '`' || chars.get(i + 1) == Some(&'(')) => {
+                substitutions.push(Substitution {
+                    context: std::mem::replace(&mut context, Context::Unquoted),
+                    opened,
+                    heredoc: heredoc.take(),
+                    depth,
+                    backquoted: c == '`',
+                });
+                depth = 0;
+                i += (c == '$') as usize;
+            }
+            (Context::Unquoted, '\\') => i += 1,
+            (Context::Unquoted, '\'') => {
+                context = Context::Single;
+                opened = i;
+            }
+            (Context::Unquoted, '"') => {
+                context = Context::Double;
+                opened = i;
+            }
+            (Context::Unquoted, '(') => depth += 1,
+            (Context::Unquoted, ')') if depth > 0 => depth -= 1,
+            (Context::Unquoted, ')') if !in_backquotes && !substitutions.is_empty() => {
+                if let Some(s) = substitutions.pop() {
+                    context = s.context;
+                    opened = s.opened;
+                    heredoc = s.heredoc;
+                    depth = s.depth;
+                }
+            }
+            (Context::Unquoted, '#') if starts_word(&chars, i) => context = Context::Comment,
+            (Context::Unquoted, '<')
+                if chars.get(i + 1) == Some(&'<') && chars.get(i + 2) != Some(&'<') =>
+            {
+                let strip_tabs = chars.get(i + 2) == Some(&'-');
+                let mut j = i + 2 + strip_tabs as usize;
+                while chars.get(j).is_some_and(|c| *c == ' ' || *c == '\t') {
+                    j += 1;
+                }
+
+                let start = j;
+                while chars
+                    .get(j)
+                    .is_some_and(|c| !c.is_whitespace() && !";&|<>()".contains(*c))
+                {
+                    j += 1;
+                }
+                let word: String = chars[start..j].iter().collect();
+                // quoting any part of the delimiter stops expansion in the body
+                let expands = !word.contains(['\'', '"', '\\']);
+                let delimiter: String = word.chars().filter(|c| !"'\"\\".contains(*c)).collect();
+
+                if !delimiter.is_empty() {
+                    pending.push((delimiter, strip_tabs, expands));
+                }
+                i = j;
+                continue;
+            }
+            (Context::Unquoted, '\n') if !pending.is_empty() => {
+                let (delimiter, strip_tabs, expands) = pending.remove(0);
+                context = Context::Here {
+                    expands,
+                    end: delimiter.clone(),
+                };
+                heredoc = Some((delimiter, strip_tabs));
+            }
+            (Context::Single, '\'') => context = Context::Unquoted,
+            (Context::Double | Context::Here { .. }, '\\') => i += 1,
+            (Context::Double, '"') => context = Context::Unquoted,
+            (Context::Comment, '\n') => {
+                context = Context::Unquoted;
+                continue;
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+
+    let backquoted = substitutions.iter().filter(|s| s.backquoted).count();
+    match context {
+        Context::Single | Context::Double => {
+            let offset = chars[..opened].iter().map(|c| c.len_utf8()).sum();
+            (context, Some(offset), backquoted)
+        }
+        _ => (context, None, backquoted),
+    }
+}
+
+/// The context at the end of the script.
+///
+/// A `$(...)` in a string that expands runs its contents as commands, so they start out unquoted.
+fn powershell_context(before: &str) -> Context {
+    let chars: Vec<char> = before.chars().collect();
+    let mut context = Context::Unquoted;
+    let mut block_comment = false;
+    let mut here_string: Option<char> = None;
+    // where each `$(` started, and the parentheses open inside it
+    let mut subexpressions: Vec<(Context, Option<char>, usize)> = Vec::new();
+    let mut depth = 0;
+    let mut i = 0;
+
+    let at_line_start = |i: usize| i == 0 || chars[i - 1] == '\n';
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        if block_comment {
+            if c == '#' && next == Some('>') {
+                block_comment = false;
+                context = Context::Unquoted;
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+
+        let expands = matches!(
+            context,
+            Context::Double | Context::Here { expands: true, .. }
+        );
+        if expands && c == '$' && next == Some('(') {
+            subexpressions.push((
+                std::mem::replace(&mut context, Context::Unquoted),
+                here_string.take(),
+                depth,
+            ));
+            depth = 0;
+            i += 2;
+            continue;
+        }
+
+        if let Some(quote) = here_string {
+            if at_line_start(i) && c == quote && next == Some('@') {
+                here_string = None;
+                context = Context::Unquoted;
+                i += 1;
+            } else if c == '`' && quote == '"' {
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+
+        match context {
+            Context::Unquoted => match c {
+                '`' => i += 1,
+                '<' if next == Some('#') => {
+                    block_comment = true;
+                    context = Context::BlockComment;
+                    i += 1;
+                }
+                '#' if starts_word(&chars, i) => context = Context::Comment,
+                '@' if next.is_some_and(|n| n == '\'' || n == '"') => {
+                    let quote = next.unwrap_or('\'');
+                    here_string = Some(quote);
+                    context = Context::Here {
+                        expands: quote == '"',
+                        end: format!("{}@", quote),
+                    };
+                    i += 1;
+                }
+                c if PS_SINGLE.contains(c) => context = Context::Single,
+                c if PS_DOUBLE.contains(c) => context = Context::Double,
+                '(' => depth += 1,
+                ')' if depth > 0 => depth -= 1,
+                ')' => {
+                    if let Some((outer, outer_here_string, outer_depth)) = subexpressions.pop() {
+                        context = outer;
+                        here_string = outer_here_string;
+                        depth = outer_depth;
+                    }
+                }
+                _ => (),
+            },
+            Context::Single if PS_SINGLE.contains(c) => context = Context::Unquoted,
+            Context::Double if c == '`' => i += 1,
+            Context::Double if PS_DOUBLE.contains(c) => context = Context::Unquoted,
+            Context::Comment if c == '\n' => context = Context::Unquoted,
+            _ => (),
+        }
+        i += 1;
+    }
+
+    context
+}
+
+fn cmd_context(before: &str) -> Context {
+    // quotes don't carry over to the next line of a batch file
+    let line = before.rsplit('\n').next().unwrap_or("");
+    let trimmed = line.trim_start().trim_start_matches('@');
+
+    if trimmed.starts_with("::")
+        || trimmed
+            .get(..4)
+            .is_some_and(|s| s.eq_ignore_ascii_case("rem "))
+    {
+        return Context::Comment;
+    }
+
+    let mut quoted = false;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '^' if !quoted => {
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            _ => (),
+        }
+    }
+
+    match quoted {
+        true => Context::Double,
+        false => Context::Unquoted,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TRICKY: &[&str] = &[
+        "plain",
+        "",
+        "a b",
+        "a b; rm -rf ~",
+        "it's",
+        "say \"hi\"",
+        "$HOME `id` $(id)",
+        "back\\slash\\",
+        "new\nline",
+        "'\"'\"",
+        "-n",
+        "*",
+        "x\necho injected",
+        "x\nEOF\necho injected",
+    ];
+
+    #[cfg(unix)]
+    fn sh(script: &str) -> String {
+        let output = std::process::Command::new("sh")
+            .arg("-c")
+            .arg(script)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", script);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn posix_values_stay_literal() {
+        let templates = [
+            "printf '%s' #{v}",
+            "printf '%s' \"#{v}\"",
+            "printf '%s' '#{v}'",
+            "printf '%s' \"pre-#{v}-post\"",
+            "sh -c 'printf \"%s\" \"$0\"' '#{v}'",
+            "sh -c 'printf \"%s\" #{v}'",
+            "sh -c \"printf '%s' #{v}\"",
+            "sh -c 'sh -c \"printf %s #{v}\"'",
+            "cat <<EOF\n#{v}\nEOF",
+            "cat <<-\"EOF\"\n\t#{v}\n\tEOF",
+            "cat <<'EOF'\n#{v}\nEOF",
+            "printf '%s' done # note #{v}",
+            "sh -c 'printf %s done # #{v}'",
+            "printf '%s' \"$(printf '%s' #{v})\"",
+            "printf '%s' \"$(printf '%s' \"#{v}\")\"",
+            "printf '%s' \"`printf '%s' #{v}`\"",
+            "printf '%s' \"`printf '%s' \"#{v}\"`\"",
+            "cat <<EOF\n$(printf '%s' #{v})\nEOF",
+        ];
+
+        for template in templates {
+            let (before, after) = template.split_once("#{v}").unwrap();
+
+            for value in TRICKY {
+                // comments and here-documents can't hold a value that would end them
+                if !Quoting::Posix.can_quote(before, value) {
+                    assert!(value.contains('\n'), "{}", template);
+                    continue;
+                }
+
+                let script = format!("{}{}{}", before, Quoting::Posix.quote(before, value), after);
+                let expected = match (template.contains("pre-"), template.contains("# ")) {
+                    (true, _) => format!("pre-{}-post", value),
+                    (_, true) => "done".to_string(),
+                    _ => value.to_string(),
+                };
+                // here-documents end with a newline
+                let expected = match template.starts_with("cat") {
+                    true => format!("{}\n", expected),
+                    false => expected,
+                };
+
+                assert_eq!(sh(&script), expected, "{}", script);
+            }
+        }
+    }
+
+    #[test]
+    fn posix_context() {
+        let q = |before: &str, value: &str| Quoting::Posix.quote(before, value);
+
+        assert_eq!(q("echo ", "/tmp/a.so"), "/tmp/a.so");
+        assert_eq!(q("grep -c '", "a b'"), r"a b'\''");
+        assert_eq!(
+            q("sudo sh -c 'echo ", "a b; rm -rf ~"),
+            r"'\''a b; rm -rf ~'\''"
+        );
+        assert_eq!(q("echo ", "a b"), "'a b'");
+        assert_eq!(q("echo ", ""), "''");
+        assert_eq!(q("echo ", "~"), "'~'");
+        assert_eq!(q("echo 'it''s' ", "x y"), "'x y'");
+        assert_eq!(q("echo \"a\\\"", "$x"), "\\$x");
+        assert_eq!(q("echo # don't ", "a b"), "a b");
+        assert_eq!(q("echo # don't\necho ", "a b"), "'a b'");
+        assert_eq!(q("cat <<'EOF'\n", "$x"), "$x");
+        assert_eq!(q("cat <<EOF\n", "$x"), "\\$x");
+        assert_eq!(q("cat <<EOF\nbody\nEOF\necho ", "a b"), "'a b'");
+        // `<<<` is a here-string, not a here-document
+        assert_eq!(q("cat <<< ", "a b"), "'a b'");
+        // a command substitution runs a new command, even inside quotes
+        assert_eq!(
+            q("echo \"$(cat ", "x; touch /tmp/arr-pwned"),
+            "'x; touch /tmp/arr-pwned'"
+        );
+        assert_eq!(q("echo \"$(cat \"", "$x"), "\\$x");
+        assert_eq!(q("echo \"$(id) ", "$x"), "\\$x");
+        assert_eq!(q("echo \"$( (id) ) ", "$x"), "\\$x");
+        assert_eq!(q("echo \"`cat ", "a b"), "'a b'");
+        assert_eq!(q("echo \"`cat ", "a\\b"), "'a\\\\b'");
+        assert_eq!(q("echo \"`id` ", "$x"), "\\$x");
+        assert_eq!(q("cat <<EOF\n$(cat ", "a b"), "'a b'");
+        assert_eq!(q("cat <<'EOF'\n$(cat ", "$x"), "$x");
+    }
+
+    #[test]
+    fn powershell_context() {
+        let q = |before: &str, value: &str| Quoting::PowerShell.quote(before, value);
+
+        assert_eq!(q("Get-Item ", "C:\\Windows\\x.dll"), "C:\\Windows\\x.dll");
+        assert_eq!(
+            q("Write-Host ", "a b; Remove-Item *"),
+            "'a b; Remove-Item *'"
+        );
+        assert_eq!(q("Write-Host ", "-Force"), "'-Force'");
+        assert_eq!(q("Write-Host ", ""), "''");
+        assert_eq!(q("Write-Host ", "it's"), "'it''s'");
+        assert_eq!(q("Write-Host ", "it\u{2019}s"), "'it\u{2019}\u{2019}s'");
+        assert_eq!(q("Write-Host 'a", "it's"), "it''s");
+        assert_eq!(
+            q("Write-Host \"a", "$env:PATH `\"x\""),
+            "`$env:PATH ```\"x`\""
+        );
+        assert_eq!(q("Write-Host \u{201c}a", "\u{201d}"), "`\u{201d}");
+        assert_eq!(q("Write-Host `\"", "a b"), "'a b'");
+        assert_eq!(q("# it's a comment ", "a b"), "a b");
+        assert_eq!(q("<# it's\n", "a b"), "a b");
+        assert_eq!(q("<# it's #> Write-Host ", "a b"), "'a b'");
+        assert_eq!(q("@'\n", "$x'"), "$x'");
+        assert_eq!(q("@\"\n", "$x"), "`$x");
+        assert_eq!(q("@'\nbody\n'@\nWrite-Host ", "a b"), "'a b'");
+        // a subexpression runs commands, even inside a string
+        assert_eq!(
+            q("Write-Host \"$(Get-Content ", "x; Remove-Item *"),
+            "'x; Remove-Item *'"
+        );
+        assert_eq!(q("Write-Host \"$((Get-Date)) ", "$x"), "`$x");
+        assert_eq!(q("@\"\n$(Get-Content ", "a b"), "'a b'");
+        assert_eq!(q("@'\n$(Get-Content ", "$x"), "$x");
+    }
+
+    #[test]
+    fn cmd_context() {
+        let q = |before: &str, value: &str| Quoting::Cmd.quote(before, value);
+
+        assert_eq!(q("dir ", "C:\\Windows"), "C:\\Windows");
+        assert_eq!(q("echo ", "a & del *"), "\"a & del *\"");
+        assert_eq!(q("echo ", "100%"), "\"100%%\"");
+        assert_eq!(q("echo ", "say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(q("echo ", ""), "\"\"");
+        assert_eq!(q("echo \"", "a & %PATH%"), "a & %%PATH%%");
+        assert_eq!(q("echo ^\"", "a b"), "\"a b\"");
+        assert_eq!(q("echo \"unclosed\r\necho ", "a b"), "\"a b\"");
+        assert_eq!(q("REM ", "a & b"), "a & b");
+        assert_eq!(q("@:: ", "a & b"), "a & b");
+
+        assert!(!Quoting::Cmd.can_quote("echo ", "a\r\ndel *"));
+    }
+
+    #[test]
+    fn values_cannot_end_comments_or_here_documents() {
+        let posix = |before: &str, value: &str| Quoting::Posix.can_quote(before, value);
+        let ps = |before: &str, value: &str| Quoting::PowerShell.can_quote(before, value);
+
+        assert!(posix("echo ", "a\nrm -rf ~"));
+        assert!(!posix("# note ", "x\nrm -rf ~"));
+        assert!(!posix("sudo sh -c 'id # ", "x\nrm -rf ~"));
+        assert!(!posix("cat <<'EOF'\n", "x\nEOF\nid"));
+        assert!(!posix("cat <<EOF\n", "x\nEOF\nid"));
+        assert!(!posix("cat <<-EOF\n", "x\n\tEOF\nid"));
+        assert!(posix("cat <<'EOF'\n", "x\nEOFS\nid"));
+        assert!(posix("cat <<'EOF'\nbody\nEOF\n# ", "x y"));
+
+        assert!(ps("Write-Host ", "a\nRemove-Item *"));
+        assert!(!ps("# note ", "x\nRemove-Item *"));
+        assert!(!ps("# note ", "x\rRemove-Item *"));
+        assert!(ps("<# note\n", "x\ny"));
+        assert!(!ps("<# note ", "x #> Remove-Item *"));
+        assert!(!ps("@'\n", "x\n'@\nRemove-Item *"));
+        assert!(!ps("@\"\n", "x\n  \"@\nRemove-Item *"));
+        assert!(ps("@'\n", "x\n\"@"));
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    error::Error,
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Serialize, Serializer};
+
+use crate::{error::ArrError, manual::ManualOutcome};
+
+/// The part of a test that a command belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Check,
+    Dependency,
+    Attack,
+    Cleanup,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Phase::Check => write!(f, "check"),
+            Phase::Dependency => write!(f, "dependency"),
+            Phase::Attack => write!(f, "attack"),
+            Phase::Cleanup => write!(f, "cleanup"),
+        }
+    }
+}
+
+/// Everything that happened when a single command ran.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PhaseReport {
+    pub phase: Phase,
+    pub command: String,
+    pub executor: String,
+    /// `None` when the command was ended by a signal, or the operator skipped
+    /// a manual test's steps
+    pub exit_code: Option<i32>,
+    pub stdout: String,
+    pub stderr: String,
+    /// What the operator reported, for a manual test
+    pub manual: Option<ManualOutcome>,
+    /// Seconds since the Unix epoch, in JSON
+    #[serde(serialize_with = "since_epoch")]
+    pub started: SystemTime,
+    #[serde(serialize_with = "since_epoch")]
+    pub ended: SystemTime,
+    /// Seconds, in JSON
+    #[serde(serialize_with = "seconds")]
+    pub duration: Duration,
+}
+
+impl PhaseReport {
+    pub fn success(&self) -> bool {
+        self.exit_code == Some(0)
+    }
+}
+
+/// Every command that ran for a test, in the order they ran.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExecutionReport {
+    pub technique: String,
+    pub test_number: usize,
+    pub phases: Vec<PhaseReport>,
+}
+
+impl ExecutionReport {
+    pub fn new(technique: &str, test_number: usize) -> Self {
+        Self {
+            technique: technique.to_string(),
+            test_number,
+            phases: Vec::new(),
+        }
+    }
+
+    /// The reports for a single phase.
+    pub fn phase(&self, phase: Phase) -> impl Iterator<Item = &PhaseReport> {
+        self.phases.iter().filter(move |p| p.phase == phase)
+    }
+}
+
+/// A test that did not finish, with every command that ran before it stopped.
+#[derive(Debug, PartialEq)]
+pub struct ExecutionFailure {
+    pub report: Box<ExecutionReport>,
+    pub source: ArrError,
+}
+
+impl fmt::Display for ExecutionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+impl Error for ExecutionFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+fn since_epoch<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    serializer.serialize_f64(since.as_secs_f64())
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
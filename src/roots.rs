@@ -0,0 +1,230 @@
+use std::path::{Path, PathBuf};
+
+use log::info;
+
+use crate::{
+    error::ArrError,
+    find_file::{find_atomics_dir, find_file},
+    index::Index,
+    parse_yaml::{parse_art_file, AtomicReadTeamTechnique},
+};
+
+/// Several atomics trees searched as one, highest priority first.
+///
+/// A technique in more than one root is merged test by test: a test in an earlier root
+/// replaces the test with the same GUID in a later one, and any other tests are added.
+pub(crate) struct Roots {
+    art_paths: Vec<PathBuf>,
+    indexes: Vec<Index>,
+}
+
+impl Roots {
+    /// Index every root, caching the indexes in `index_dir` if there is one.
+    pub fn open(art_paths: &[PathBuf], index_dir: Option<&Path>) -> Result<Self, ArrError> {
+        // like the CLI, look in the current directory when no paths are given
+        let default = [PathBuf::from(".")];
+        let art_paths = match art_paths.is_empty() {
+            true => &default[..],
+            false => art_paths,
+        };
+
+        let indexes = art_paths
+            .iter()
+            .map(|art_path| Index::open(art_path, index_dir))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            art_paths: art_paths.to_vec(),
+            indexes,
+        })
+    }
+
+    /// The ID of every technique in any root, sorted.
+    pub fn techniques(&self) -> Vec<String> {
+        let mut techniques: Vec<String> = Vec::new();
+
+        for f in self.indexes.iter().flat_map(Index::files) {
+            if !techniques
+                .iter()
+                .any(|t| t.eq_ignore_ascii_case(&f.technique))
+            {
+                techniques.push(f.technique.clone());
+            }
+        }
+        techniques.sort();
+
+        techniques
+    }
+
+    /// Load a technique from every root that has it.
+    pub fn technique(&self, technique: &str) -> Result<AtomicReadTeamTechnique, ArrError> {
+        let mut merged: Option<AtomicReadTeamTechnique> = None;
+
+        // lowest priority first, so earlier roots are laid over later ones
+        for index in self.indexes.iter().rev() {
+            if let Some(path) = index.technique_file(technique) {
+                info!(
+                    "Located matching YAML file for {}: {}",
+                    &technique,
+                    &path.to_string_lossy()
+                );
+
+                let yaml = load(path, index.atomics_dir())?;
+                merged = Some(match merged {
+                    Some(base) => overlay(base, yaml),
+                    None => yaml,
+                });
+            }
+        }
+
+        match merged {
+            Some(yaml) => Ok(yaml),
+            // the file may live outside the directories the indexes watch
+            None => {
+                for art_path in &self.art_paths {
+                    if let Ok(path) = find_file(technique, art_path) {
+                        return load(&path, &find_atomics_dir(art_path)?);
+                    }
+                }
+                Err(ArrError::CannotLocateYamlFile)
+            }
+        }
+    }
+}
+
+fn load(path: &Path, atomics_dir: &Path) -> Result<AtomicReadTeamTechnique, ArrError> {
+    let mut yaml = parse_art_file(path)?;
+
+    for test in &mut yaml.atomic_tests {
+        test.atomics_dir = atomics_dir.to_owned();
+        test.technique_dir = path.parent().unwrap_or(atomics_dir).to_owned();
+    }
+
+    Ok(yaml)
+}
+
+fn overlay(
+    mut base: AtomicReadTeamTechnique,
+    overlay: AtomicReadTeamTechnique,
+) -> AtomicReadTeamTechnique {
+    base.display_name = overlay.display_name;
+
+    for test in overlay.atomic_tests {
+        match base
+            .atomic_tests
+            .iter()
+            .position(|t| t.auto_generated_guid == test.auto_generated_guid)
+        {
+            Some(i) => {
+                info!(
+                    "{} test {} is overridden by {}",
+                    &base.attack_technique,
+                    i + 1,
+                    &test.atomics_dir.to_string_lossy()
+                );
+                base.atomic_tests[i] = test;
+            }
+            None => base.atomic_tests.push(test),
+        }
+    }
+
+    base
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_support::write_atomics;
+    use tempfile::TempDir;
+
+    fn root(technique: &str, tests: &[(&str, &str)]) -> TempDir {
+        let tests: String = tests
+            .iter()
+            .map(|(name, guid)| {
+                format!(
+                    "- name: {}
+  auto_generated_guid: {}
+  description: A test
+  supported_platforms:
+  - linux
+  executor:
+    command: echo PathToAtomicsFolder
+    name: sh
+",
+                    name, guid
+                )
+            })
+            .collect();
+
+        let art_path = tempfile::tempdir().unwrap();
+        let yaml = format!(
+            "attack_technique: {}\ndisplay_name: {}\natomic_tests:\n{}",
+            technique,
+            art_path.path().to_string_lossy(),
+            tests
+        );
+        write_atomics(art_path.path(), &[(technique, &yaml)], "");
+
+        art_path
+    }
+
+    #[test]
+    fn earlier_roots_win() {
+        let upstream = root(
+            "T0000.007",
+            &[
+                ("Upstream One", "00000000-0000-0000-0000-000000000071"),
+                ("Upstream Two", "00000000-0000-0000-0000-000000000072"),
+            ],
+        );
+        let custom = root(
+            "T0000.007",
+            &[
+                ("Custom Two", "00000000-0000-0000-0000-000000000072"),
+                ("Custom Three", "00000000-0000-0000-0000-000000000073"),
+            ],
+        );
+        let other = root(
+            "T0000.008",
+            &[("Other", "00000000-0000-0000-0000-000000000081")],
+        );
+
+        let roots = Roots::open(
+            &[
+                custom.path().to_owned(),
+                upstream.path().to_owned(),
+                other.path().to_owned(),
+            ],
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(roots.techniques(), vec!["T0000.007", "T0000.008"]);
+
+        let yaml = roots.technique("T0000.007").unwrap();
+        let tests: Vec<(&str, PathBuf)> = yaml
+            .atomic_tests
+            .iter()
+            .map(|t| (t.name.as_str(), t.atomics_dir.clone()))
+            .collect();
+
+        assert_eq!(yaml.display_name, custom.path().to_string_lossy());
+        assert_eq!(
+            tests,
+            vec![
+                ("Upstream One", upstream.path().join("atomics")),
+                ("Custom Two", custom.path().join("atomics")),
+                ("Custom Three", custom.path().join("atomics")),
+            ]
+        );
+
+        assert_eq!(
+            roots.technique("T0000.008").unwrap().atomic_tests[0].atomics_dir,
+            other.path().join("atomics")
+        );
+        assert_eq!(
+            roots.technique("T0000.009"),
+            Err(ArrError::CannotLocateYamlFile)
+        );
+    }
+}
@@ -0,0 +1,373 @@
+use std::{
+    any::Any,
+    collections::{HashSet, VecDeque},
+    panic::{self, AssertUnwindSafe},
+    sync::mpsc,
+    thread,
+};
+
+use log::{error, info};
+
+use crate::{
+    argument::ArgType, parse_yaml::AtomicTest, process::interrupted, Arr, ArrError, TestOutcome,
+    TestResult,
+};
+
+/// Limits on when a task may run alongside other tasks.
+#[derive(Debug, Default, Clone)]
+pub struct Constraints {
+    /// The task must be the only one running
+    pub exclusive: bool,
+    /// Tasks that share any of these paths never run at the same time
+    pub shared_paths: HashSet<String>,
+}
+
+impl Constraints {
+    fn conflicts_with(&self, other: &Constraints) -> bool {
+        self.exclusive || other.exclusive || !self.shared_paths.is_disjoint(&other.shared_paths)
+    }
+}
+
+/// A single test waiting to be scheduled.
+pub struct Job {
+    pub arr: Arr,
+    pub technique: String,
+    pub test_number: usize,
+    pub test_name: String,
+    pub cleanup: bool,
+    /// Print a line for the test once it finishes
+    pub progress: bool,
+    pub constraints: Constraints,
+}
+
+impl Job {
+    pub(crate) fn from_test(arr: Arr, technique: &str, test_num: usize, test: &AtomicTest) -> Self {
+        // manual tests need the operator's full attention
+        let exclusive = test.executor.elevation_required.unwrap_or(false)
+            || test.executor.name.eq_ignore_ascii_case("manual");
+
+        let shared_paths = test
+            .input_arguments
+            .values()
+            .filter(|arg| arg.arg_type == ArgType::Path)
+            .map(|arg| arg.default.trim().to_lowercase())
+            .filter(|path| !path.is_empty())
+            .collect();
+
+        Self {
+            arr,
+            technique: technique.to_string(),
+            test_number: test_num + 1,
+            test_name: test.name.clone(),
+            cleanup: false,
+            progress: true,
+            constraints: Constraints {
+                exclusive,
+                shared_paths,
+            },
+        }
+    }
+
+    fn run(self) -> TestResult {
+        let (technique, test_number, test_name, progress) = (
+            self.technique.clone(),
+            self.test_number,
+            self.test_name.clone(),
+            self.progress,
+        );
+
+        // a test that panics fails on its own instead of taking the run down with it
+        match panic::catch_unwind(AssertUnwindSafe(|| self.run_test())) {
+            Ok(result) => result,
+            Err(payload) => {
+                let e = ArrError::Other(format!("the test panicked: {}", panic_message(&payload)));
+                if progress {
+                    println!(
+                        "Running: {}, Test: {}...\tFailure: {}",
+                        technique, test_number, e
+                    );
+                }
+                TestResult {
+                    technique,
+                    test_number,
+                    test_name,
+                    outcome: TestOutcome::Failed(e),
+                    report: None,
+                }
+            }
+        }
+    }
+
+    fn run_test(self) -> TestResult {
+        // once interrupted, let the running tests clean up but don't start new ones
+        let (mut report, res) = match interrupted() {
+            true => (None, Err(ArrError::Interrupted)),
+            false => match self.arr.run() {
+                Ok(report) => (Some(report), Ok(())),
+                Err(failure) => (Some(*failure.report), Err(failure.source)),
+            },
+        };
+
+        let outcome = match res {
+            Ok(_) => {
+                if self.cleanup {
+                    let cleanup = self.arr.cleanup().unwrap_or_else(|failure| {
+                        error!("Cleanup failed: {}", failure);
+                        *failure.report
+                    });
+                    if let Some(report) = &mut report {
+                        report.phases.extend(cleanup.phases);
+                    }
+                }
+                TestOutcome::Passed
+            }
+            Err(
+                e @ (ArrError::RootRequired | ArrError::ManualStepsSkipped | ArrError::Interrupted),
+            ) => TestOutcome::Skipped(e),
+            Err(e) => TestOutcome::Failed(e),
+        };
+
+        // print the whole line at once so results from different tests never interleave
+        let status = match &outcome {
+            TestOutcome::Passed => "Success!".to_string(),
+            TestOutcome::Failed(e) => format!("Failure: {}", e),
+            TestOutcome::Skipped(e) => format!("Skipped: {}", e),
+        };
+        if self.progress {
+            println!(
+                "Running: {}, Test: {}...\t{}",
+                self.technique, self.test_number, status
+            );
+        }
+
+        TestResult {
+            technique: self.technique,
+            test_number: self.test_number,
+            test_name: self.test_name,
+            outcome,
+            report,
+        }
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .unwrap_or("unknown cause"),
+    }
+}
+
+/// Run the jobs with at most `max_jobs` running at once.
+///
+/// Results are returned in the same order as the jobs.
+pub fn run_jobs(jobs: Vec<Job>, max_jobs: usize) -> Vec<TestResult> {
+    let tasks = jobs
+        .into_iter()
+        .map(|job| {
+            let constraints = job.constraints.clone();
+            (job, constraints)
+        })
+        .collect();
+
+    schedule(tasks, max_jobs, Job::run)
+}
+
+/// Tells the scheduler a worker has finished, even if its task panicked, so waiting can't hang.
+struct Finished<R> {
+    i: usize,
+    result: Option<R>,
+    tx: mpsc::Sender<(usize, Option<R>)>,
+}
+
+impl<R> Drop for Finished<R> {
+    fn drop(&mut self) {
+        let _ = self.tx.send((self.i, self.result.take()));
+    }
+}
+
+/// Run `f` on every task, returning what it returned in the same order as the tasks.
+///
+/// `f` is expected not to panic; if it does, the panic is raised again once the other tasks finish.
+fn schedule<T, R, F>(tasks: Vec<(T, Constraints)>, max_jobs: usize, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync,
+{
+    let max_jobs = max_jobs.max(1);
+    let task_count = tasks.len();
+
+    let mut pending: VecDeque<(usize, T, Constraints)> = tasks
+        .into_iter()
+        .enumerate()
+        .map(|(i, (task, constraints))| (i, task, constraints))
+        .collect();
+    let mut running: Vec<(usize, Constraints)> = Vec::new();
+    let mut results: Vec<Option<R>> = (0..task_count).map(|_| None).collect();
+
+    info!("Scheduling {} tasks with {} workers", task_count, max_jobs);
+
+    thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel();
+        let f = &f;
+
+        while !pending.is_empty() || !running.is_empty() {
+            // start every pending task that fits, in order
+            if running.len() < max_jobs {
+                let next = pending.iter().position(|(_, _, constraints)| {
+                    running
+                        .iter()
+                        .all(|(_, other)| !constraints.conflicts_with(other))
+                });
+
+                if let Some(pos) = next {
+                    let (i, task, constraints) = pending.remove(pos).unwrap();
+                    running.push((i, constraints));
+
+                    let tx = tx.clone();
+                    scope.spawn(move || {
+                        let mut finished = Finished {
+                            i,
+                            result: None,
+                            tx,
+                        };
+                        finished.result = Some(f(task));
+                    });
+                    continue;
+                }
+            }
+
+            // wait for a running task to finish
+            let (i, result) = rx.recv().expect("every worker sends its result");
+            running.retain(|(r, _)| *r != i);
+            results[i] = result;
+        }
+    });
+
+    results.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    fn paths(paths: &[&str]) -> HashSet<String> {
+        paths.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn results_keep_order() {
+        let tasks = (0..10).map(|i| (i, Constraints::default())).collect();
+
+        assert_eq!(
+            schedule(tasks, 4, |i| i * 2),
+            (0..10).map(|i| i * 2).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn respects_max_jobs() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let tasks = (0..12).map(|i| (i, Constraints::default())).collect();
+
+        schedule(tasks, 3, |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+
+        assert!(peak.load(Ordering::SeqCst) <= 3);
+    }
+
+    #[test]
+    fn exclusive_runs_alone() {
+        let running = AtomicUsize::new(0);
+        let tasks = (0..8)
+            .map(|i| {
+                let constraints = Constraints {
+                    exclusive: i % 3 == 0,
+                    ..Default::default()
+                };
+                (constraints.exclusive, constraints)
+            })
+            .collect();
+
+        let overlapped = schedule(tasks, 4, |exclusive| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            thread::sleep(Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+            exclusive && now > 1
+        });
+
+        assert!(overlapped.iter().all(|o| !o));
+    }
+
+    #[test]
+    fn shared_paths_are_serialized() {
+        let running = AtomicUsize::new(0);
+        let tasks = (0..6)
+            .map(|_| {
+                let constraints = Constraints {
+                    shared_paths: paths(&["/tmp/t1574006.so"]),
+                    ..Default::default()
+                };
+                ((), constraints)
+            })
+            .collect();
+
+        let peaks = schedule(tasks, 4, |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, Ordering::SeqCst);
+            now
+        });
+
+        assert!(peaks.iter().all(|p| *p == 1));
+    }
+
+    #[test]
+    fn panics_do_not_hang() {
+        let finished = AtomicUsize::new(0);
+        let tasks = (0..6).map(|i| (i, Constraints::default())).collect();
+
+        let res = panic::catch_unwind(AssertUnwindSafe(|| {
+            schedule(tasks, 2, |i| match i {
+                3 => panic!("task {} failed", i),
+                _ => finished.fetch_add(1, Ordering::SeqCst),
+            })
+        }));
+
+        assert!(res.is_err());
+        // the other tasks still ran
+        assert_eq!(finished.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn conflicts() {
+        let a = Constraints {
+            exclusive: false,
+            shared_paths: paths(&["/tmp/a"]),
+        };
+        let b = Constraints {
+            exclusive: false,
+            shared_paths: paths(&["/tmp/b"]),
+        };
+        let c = Constraints {
+            exclusive: true,
+            shared_paths: HashSet::new(),
+        };
+
+        assert!(!a.conflicts_with(&b));
+        assert!(a.conflicts_with(&a.clone()));
+        assert!(a.conflicts_with(&c));
+    }
+}
@@ -0,0 +1,311 @@
+use std::{fmt, ops::RangeInclusive, str::FromStr};
+
+use log::{error, info};
+
+use crate::{error::ArrError, parse_yaml::AtomicReadTeamTechnique};
+
+/// Picks tests out of a technique.
+///
+/// Parsed from the command line as one of:
+/// - one-based test numbers and ranges, e.g. `1,3-5`
+/// - an `auto_generated_guid`, optionally prefixed with `guid:`
+/// - a test name, optionally prefixed with `name:`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TestSelector {
+    Numbers(Vec<RangeInclusive<usize>>),
+    Guid(String),
+    /// An exact name, or failing that, a name containing every word
+    Name(String),
+}
+
+impl TestSelector {
+    /// The zero-based positions of the selected tests.
+    pub(crate) fn resolve(
+        &self,
+        technique: &AtomicReadTeamTechnique,
+    ) -> Result<Vec<usize>, ArrError> {
+        let tests = &technique.atomic_tests;
+
+        let selected = match self {
+            TestSelector::Numbers(ranges) => {
+                let mut selected: Vec<usize> = Vec::new();
+                for number in ranges.iter().flat_map(|r| r.clone()) {
+                    if number == 0 || number > tests.len() {
+                        return Err(self.not_found(technique));
+                    }
+                    if !selected.contains(&(number - 1)) {
+                        selected.push(number - 1);
+                    }
+                }
+                selected
+            }
+            TestSelector::Guid(guid) => tests
+                .iter()
+                .position(|t| t.auto_generated_guid.eq_ignore_ascii_case(guid))
+                .into_iter()
+                .collect(),
+            TestSelector::Name(name) => {
+                let exact: Vec<usize> = (0..tests.len())
+                    .filter(|&i| tests[i].name.trim().eq_ignore_ascii_case(name.trim()))
+                    .collect();
+
+                match exact.is_empty() {
+                    false => exact,
+                    true => {
+                        let words: Vec<String> =
+                            name.split_whitespace().map(str::to_lowercase).collect();
+
+                        let fuzzy: Vec<usize> = (0..tests.len())
+                            .filter(|&i| {
+                                let test_name = tests[i].name.to_lowercase();
+                                words.iter().all(|w| test_name.contains(w.as_str()))
+                            })
+                            .collect();
+
+                        if fuzzy.len() > 1 {
+                            let candidates = fuzzy
+                                .iter()
+                                .map(|&i| format!("{}: {}", i + 1, tests[i].name))
+                                .collect();
+                            error!("`{}` matches more than one test", name);
+                            return Err(ArrError::AmbiguousTestName(name.clone(), candidates));
+                        }
+
+                        fuzzy
+                    }
+                }
+            }
+        };
+
+        if selected.is_empty() {
+            return Err(self.not_found(technique));
+        }
+
+        info!(
+            "Selected {} test(s) {:?} with `{}`",
+            &technique.attack_technique,
+            selected.iter().map(|i| i + 1).collect::<Vec<_>>(),
+            self
+        );
+
+        Ok(selected)
+    }
+
+    fn not_found(&self, technique: &AtomicReadTeamTechnique) -> ArrError {
+        error!(
+            "No test in {} matches `{}`",
+            &technique.attack_technique, self
+        );
+        technique.test_not_found(&self.to_string())
+    }
+}
+
+impl Default for TestSelector {
+    fn default() -> Self {
+        TestSelector::Numbers(vec![1..=1])
+    }
+}
+
+impl fmt::Display for TestSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestSelector::Numbers(ranges) => {
+                let ranges: Vec<String> = ranges
+                    .iter()
+                    .map(|r| match r.start() == r.end() {
+                        true => r.start().to_string(),
+                        false => format!("{}-{}", r.start(), r.end()),
+                    })
+                    .collect();
+                write!(f, "{}", ranges.join(","))
+            }
+            TestSelector::Guid(guid) => write!(f, "guid:{}", guid),
+            TestSelector::Name(name) => write!(f, "name:{}", name),
+        }
+    }
+}
+
+impl FromStr for TestSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        if s.is_empty() {
+            return Err("Please specify a test number, GUID or name".to_string());
+        }
+
+        if let Some(guid) = s.strip_prefix("guid:") {
+            return match is_guid(guid.trim()) {
+                true => Ok(TestSelector::Guid(guid.trim().to_lowercase())),
+                false => Err(format!("`{}` is not a GUID", guid)),
+            };
+        }
+
+        if let Some(name) = s.strip_prefix("name:") {
+            return Ok(TestSelector::Name(name.trim().to_string()));
+        }
+
+        if is_guid(s) {
+            return Ok(TestSelector::Guid(s.to_lowercase()));
+        }
+
+        if s.chars()
+            .all(|c| c.is_ascii_digit() || c == ',' || c == '-' || c == ' ')
+        {
+            return parse_numbers(s).map(TestSelector::Numbers);
+        }
+
+        Ok(TestSelector::Name(s.to_string()))
+    }
+}
+
+fn parse_numbers(s: &str) -> Result<Vec<RangeInclusive<usize>>, String> {
+    let number = |n: &str| {
+        n.trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|n| *n > 0)
+            .ok_or_else(|| format!("`{}` is not a test number (they start at 1)", n.trim()))
+    };
+
+    s.split(',')
+        .map(|part| match part.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (number(start)?, number(end)?);
+                match start <= end {
+                    true => Ok(start..=end),
+                    false => Err(format!("`{}` is not a valid range", part.trim())),
+                }
+            }
+            None => number(part).map(|n| n..=n),
+        })
+        .collect()
+}
+
+pub(crate) fn is_guid(s: &str) -> bool {
+    let groups: Vec<&str> = s.split('-').collect();
+
+    groups.len() == 5
+        && groups
+            .iter()
+            .zip([8, 4, 4, 4, 12])
+            .all(|(g, len)| g.len() == len && g.chars().all(|c| c.is_ascii_hexdigit()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn technique() -> AtomicReadTeamTechnique {
+        let test = |name: &str, guid: &str| {
+            format!(
+                "- name: {}
+  auto_generated_guid: {}
+  description: A test
+  supported_platforms:
+  - linux
+  executor:
+    command: id
+    name: sh
+",
+                name, guid
+            )
+        };
+
+        serde_yaml::from_str(&format!(
+            "attack_technique: T1003.008
+display_name: 'OS Credential Dumping: /etc/passwd and /etc/shadow'
+atomic_tests:
+{}{}{}{}",
+            test(
+                "Access /etc/shadow (Local)",
+                "3723ab77-c546-403c-8fb4-bb577033b235"
+            ),
+            test(
+                "Access /etc/passwd (Local)",
+                "60e860b6-8ae6-49db-ad07-5e73edd88f5d"
+            ),
+            test(
+                "Access /etc/{shadow,passwd} with a standard bin that's not cat",
+                "df1a55ae-019d-4120-bc35-94f4bc5c4b0a"
+            ),
+            test(
+                "Access /etc/{shadow,passwd} with shell builtins",
+                "f5aa6543-6cb2-4fae-b9c2-b96e14721713"
+            )
+        ))
+        .unwrap()
+    }
+
+    fn resolve(s: &str) -> Result<Vec<usize>, ArrError> {
+        s.parse::<TestSelector>().unwrap().resolve(&technique())
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("2".parse(), Ok(TestSelector::Numbers(vec![2..=2])));
+        assert_eq!(
+            "1,3-4".parse(),
+            Ok(TestSelector::Numbers(vec![1..=1, 3..=4]))
+        );
+        assert_eq!(
+            "60E860B6-8AE6-49DB-AD07-5E73EDD88F5D".parse(),
+            Ok(TestSelector::Guid(
+                "60e860b6-8ae6-49db-ad07-5e73edd88f5d".to_string()
+            ))
+        );
+        assert_eq!("name:2".parse(), Ok(TestSelector::Name("2".to_string())));
+        assert_eq!(
+            "Access /etc/passwd (Local)".parse(),
+            Ok(TestSelector::Name("Access /etc/passwd (Local)".to_string()))
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!("0".parse::<TestSelector>().is_err());
+        assert!("4-2".parse::<TestSelector>().is_err());
+        assert!("1,,2".parse::<TestSelector>().is_err());
+        assert!("guid:1234".parse::<TestSelector>().is_err());
+    }
+
+    #[test]
+    fn numbers() {
+        assert_eq!(resolve("1,3-4"), Ok(vec![0, 2, 3]));
+        assert_eq!(resolve("2,1-2"), Ok(vec![1, 0]));
+        assert!(matches!(
+            resolve("5"),
+            Err(ArrError::TestNotFound { requested, available, .. })
+                if requested == "5" && available.len() == 4
+        ));
+    }
+
+    #[test]
+    fn guid() {
+        assert_eq!(resolve("f5aa6543-6cb2-4fae-b9c2-b96e14721713"), Ok(vec![3]));
+        assert!(resolve("guid:00000000-0000-0000-0000-000000000000").is_err());
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(resolve("access /etc/passwd (local)"), Ok(vec![1]));
+        assert_eq!(resolve("shell builtins"), Ok(vec![3]));
+        assert!(matches!(
+            resolve("name:shadow"),
+            Err(ArrError::AmbiguousTestName(_, candidates)) if candidates.len() == 3
+        ));
+        assert!(resolve("kerberoast").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in [
+            "1,3-5",
+            "guid:3723ab77-c546-403c-8fb4-bb577033b235",
+            "name:Access",
+        ] {
+            assert_eq!(s.parse::<TestSelector>().unwrap().to_string(), s);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Fixtures shared by the tests.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+
+/// Write the techniques, given as `(id, yaml)`, to an atomics tree in `art_path`.
+///
+/// Returns the atomics directory.
+pub fn write_atomics(art_path: &Path, techniques: &[(&str, &str)], used_guids: &str) -> PathBuf {
+    let atomics = art_path.join("atomics");
+    fs::create_dir_all(&atomics).unwrap();
+    fs::write(atomics.join("used_guids.txt"), used_guids).unwrap();
+
+    for (id, yaml) in techniques {
+        fs::create_dir_all(atomics.join(id)).unwrap();
+        fs::write(technique_file(&atomics, id), yaml).unwrap();
+    }
+
+    atomics
+}
+
+/// Where a technique's YAML file is in an atomics directory.
+pub fn technique_file(atomics: &Path, id: &str) -> PathBuf {
+    atomics.join(id).join(format!("{}.yaml", id))
+}
+
+/// Write a one technique atomics tree to a temporary directory.
+pub fn corpus(technique: &str, yaml: &str) -> TempDir {
+    let art_path = tempfile::tempdir().unwrap();
+    write_atomics(art_path.path(), &[(technique, yaml)], "");
+
+    art_path
+}
@@ -1,6 +1,22 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
 
-use crate::{find_file::all_techniques, parse_yaml::parse_art_file, ArrError};
+use log::{error, info};
+use serde::{ser::SerializeStruct, Serialize, Serializer};
+
+use crate::{
+    executor::executor_names,
+    find_file::all_techniques,
+    index::Index,
+    parse_yaml::parse_art_file,
+    roots::Roots,
+    scheduler::{run_jobs, Job},
+    selector::TestSelector,
+    supports_os, unknown_name, vars_for, Arr, ArrError, ExecutionReport, Ledger, LedgerEntry,
+    Timeouts, VarSources,
+};
 
 pub fn parse_all(art_path: &Path) {
     for f in all_techniques(art_path) {
@@ -12,80 +28,399 @@ pub fn parse_all(art_path: &Path) {
 
         match res {
             Ok(_) => println!("Success!"),
-            Err(_) => {
-                return;
-            }
+            Err(e) => println!("Failure: {}", e),
         }
     }
 }
 
-pub fn get_all_executors(art_path: &Path) -> Result<(), ArrError> {
+pub fn get_all_executors(art_path: &Path, index_dir: Option<&Path>) -> Result<(), ArrError> {
     let mut executor_count: HashMap<String, usize> = HashMap::new();
     let mut dep_executor_count: HashMap<String, usize> = HashMap::new();
 
-    for f in all_techniques(art_path) {
-        let technique = parse_art_file(&f.into_path())?;
-
-        for art_test in technique.atomic_tests {
-            let count = executor_count.entry(art_test.executor.name).or_insert(0);
+    for f in Index::open(art_path, index_dir)?.files() {
+        for art_test in &f.tests {
+            let count = executor_count.entry(art_test.executor.clone()).or_insert(0);
             *count += 1;
 
-            if let Some(dep_executor) = art_test.dependency_executor_name {
+            if let Some(dep_executor) = art_test.dependency_executor.clone() {
                 let count = dep_executor_count.entry(dep_executor).or_insert(0);
                 *count += 1;
             }
         }
     }
 
+    // flag any executor that arr doesn't know how to run
+    let supported = |name: &str| match executor_names().any(|e| e.eq_ignore_ascii_case(name)) {
+        true => "",
+        false => " (unsupported)",
+    };
+
     println!("Executors:");
     for (key, value) in executor_count.iter() {
-        println!("{}: {}", key, value);
+        println!("{}: {}{}", key, value, supported(key));
     }
 
     println!("\nDependency Executors:");
     for (key, value) in dep_executor_count.iter() {
-        println!("{}: {}", key, value);
+        println!("{}: {}{}", key, value, supported(key));
     }
 
     Ok(())
 }
 
-// pub fn run_all(art_path: &Path) {
-//     for f in all_techniques(art_path) {
-//         let technique = match parse_atr_file(&f.clone().into_path()) {
-//             Ok(technique) => technique,
-//             Err(e) => {
-//                 eprintln!("{}", e.to_string());
-//                 return;
-//             }
-//         };
-
-//         for (test_num, _) in technique.atomic_tests.iter().enumerate() {
-//             println!(
-//                 "Running: {}, Test: {}...\t",
-//                 technique.attack_technique, test_num
-//             );
-
-//             let file_name = f
-//                 .clone()
-//                 .file_name()
-//                 .to_string_lossy()
-//                 .strip_suffix(".yaml")
-//                 .unwrap()
-//                 .to_string();
-
-//             let mut arr = Arr::new(file_name, HashMap::new(), test_num, art_path.to_owned());
-
-//             match arr.run() {
-//                 Ok(_) => println!("Success!"),
-//                 Err(e) => match e {
-//                     ArrError::OsNotSupported(_) => continue,
-//                     _ => {
-//                         eprintln!("Failure:\n\n{}", e.to_string());
-//                         return;
-//                     }
-//                 },
-//             }
-//         }
-//     }
-// }
+#[derive(Debug)]
+pub enum TestOutcome {
+    Passed,
+    Failed(ArrError),
+    Skipped(ArrError),
+}
+
+/// Written as its `status`, and the `error` for a test that failed or was skipped.
+impl Serialize for TestOutcome {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (status, error) = match self {
+            TestOutcome::Passed => ("passed", None),
+            TestOutcome::Failed(e) => ("failed", Some(e.to_string())),
+            TestOutcome::Skipped(e) => ("skipped", Some(e.to_string())),
+        };
+
+        let mut outcome = serializer.serialize_struct("TestOutcome", 2)?;
+        outcome.serialize_field("status", status)?;
+        outcome.serialize_field("error", &error)?;
+        outcome.end()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TestResult {
+    pub technique: String,
+    pub test_number: usize,
+    pub test_name: String,
+    #[serde(flatten)]
+    pub outcome: TestOutcome,
+    /// Every command that ran, unless the test was skipped before running any
+    pub report: Option<ExecutionReport>,
+}
+
+/// How `run_all` runs each test.
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    /// Run each test's cleanup after it succeeds
+    pub cleanup: bool,
+    /// Run each test's cleanup when its attack fails
+    pub cleanup_on_failure: bool,
+    /// The most tests to run at the same time
+    pub max_jobs: usize,
+    pub timeouts: Timeouts,
+    pub ledger: Option<Ledger>,
+    /// Where to cache the technique indexes, rather than indexing the roots each time
+    pub index_dir: Option<PathBuf>,
+    /// Values from `--set-var` for the arguments of whichever tests take them
+    pub vars: HashMap<String, String>,
+    /// Values for the arguments of whichever tests take them
+    pub var_sources: VarSources,
+    /// Substitute the variables as given, rather than quoted for the executor's shell
+    pub raw_vars: bool,
+    /// Print a line for each test as it finishes
+    pub progress: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            cleanup: false,
+            cleanup_on_failure: false,
+            max_jobs: 1,
+            timeouts: Timeouts::default(),
+            ledger: None,
+            index_dir: None,
+            vars: HashMap::new(),
+            var_sources: VarSources::default(),
+            raw_vars: false,
+            progress: true,
+        }
+    }
+}
+
+pub fn run_all(art_paths: &[PathBuf], options: &RunOptions) -> Result<Vec<TestResult>, ArrError> {
+    let mut results: Vec<TestResult> = Vec::new();
+    let mut jobs = Vec::new();
+    // the arguments any test takes, to catch variables meant for none of them
+    let mut arguments = HashSet::new();
+
+    let roots = Roots::open(art_paths, options.index_dir.as_deref())?;
+
+    for id in roots.techniques() {
+        let technique = match roots.technique(&id) {
+            Ok(technique) => technique,
+            Err(e) => {
+                error!("Skipping {}: {}", &id, e);
+                continue;
+            }
+        };
+
+        for (test_num, test) in technique.atomic_tests.iter().enumerate() {
+            arguments.extend(test.input_arguments.keys().cloned());
+
+            if !supports_os(test) {
+                if options.progress {
+                    println!(
+                        "Running: {}, Test: {}...\tSkipped: {}",
+                        technique.attack_technique,
+                        test_num + 1,
+                        ArrError::OsNotSupported
+                    );
+                }
+
+                results.push(TestResult {
+                    technique: technique.attack_technique.clone(),
+                    test_number: test_num + 1,
+                    test_name: test.name.clone(),
+                    outcome: TestOutcome::Skipped(ArrError::OsNotSupported),
+                    report: None,
+                });
+                continue;
+            }
+
+            let mut arr = Arr::new(
+                technique.attack_technique.clone(),
+                vars_for(test, &options.vars),
+                test_num,
+                art_paths.to_vec(),
+            )
+            .with_timeouts(options.timeouts)
+            .with_cleanup_on_failure(options.cleanup_on_failure)
+            .with_var_sources(options.var_sources.clone())
+            .with_raw_vars(options.raw_vars)
+            .with_index_dir(options.index_dir.clone());
+
+            if let Some(ledger) = &options.ledger {
+                arr = arr.with_ledger(ledger.clone());
+            }
+
+            let mut job = Job::from_test(arr, &technique.attack_technique, test_num, test);
+            job.cleanup = options.cleanup && test.executor.cleanup_command.is_some();
+            job.progress = options.progress;
+
+            jobs.push(job);
+        }
+    }
+
+    if let Some(name) = options.vars.keys().find(|name| !arguments.contains(*name)) {
+        return Err(unknown_name(name, arguments.into_iter().collect()));
+    }
+
+    results.extend(run_jobs(jobs, options.max_jobs));
+
+    // present the results in corpus order, regardless of when they finished
+    results.sort_by(|a, b| (&a.technique, a.test_number).cmp(&(&b.technique, b.test_number)));
+
+    Ok(results)
+}
+
+/// A ledger entry and how its cleanup went.
+pub type PendingCleanup = (LedgerEntry, Result<ExecutionReport, ArrError>);
+
+/// Run the cleanup for every attack in the ledger, forgetting the ones that succeed.
+pub fn cleanup_pending(
+    ledger: &Ledger,
+    art_paths: &[PathBuf],
+    index_dir: Option<&Path>,
+    timeouts: Timeouts,
+) -> Result<Vec<PendingCleanup>, ArrError> {
+    let roots = Roots::open(art_paths, index_dir)?;
+    let mut results = Vec::new();
+
+    for entry in ledger.entries()? {
+        info!(
+            "Cleaning up {} test {} from {}",
+            &entry.technique, entry.test_number, entry.timestamp
+        );
+
+        let res = pending_test(&entry, &roots).and_then(|(test_num, vars)| {
+            Arr::new(entry.technique.clone(), vars, test_num, art_paths.to_vec())
+                .with_timeouts(timeouts)
+                .with_index_dir(index_dir.map(Path::to_path_buf))
+                .with_raw_vars(entry.raw_vars)
+                .with_defaults(entry.defaults())
+                .with_ledger(ledger.clone())
+                .cleanup()
+                .map_err(|failure| failure.source)
+        });
+
+        results.push((entry, res));
+    }
+
+    Ok(results)
+}
+
+/// The test's position may have changed since it ran, so look it up by GUID, and drop any
+/// variables it no longer takes.
+///
+/// A test that is gone is not guessed at by position, which could clean up another test.
+fn pending_test(
+    entry: &LedgerEntry,
+    roots: &Roots,
+) -> Result<(usize, HashMap<String, String>), ArrError> {
+    let technique = roots.technique(&entry.technique)?;
+    let test_num = TestSelector::Guid(entry.guid.clone()).resolve(&technique)?[0];
+
+    Ok((
+        test_num,
+        vars_for(&technique.atomic_tests[test_num], &entry.set_vars()),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{test_support::corpus, Phase};
+
+    const TECHNIQUE: &str = "attack_technique: T0000.001
+display_name: Run All
+atomic_tests:
+- name: Passes
+  auto_generated_guid: 00000000-0000-0000-0000-000000000001
+  description: Exits successfully
+  supported_platforms:
+  - linux
+  - macos
+  executor:
+    command: exit 0
+    name: sh
+- name: Fails
+  auto_generated_guid: 00000000-0000-0000-0000-000000000002
+  description: Exits with an error
+  supported_platforms:
+  - linux
+  - macos
+  executor:
+    command: exit 1
+    name: sh
+- name: Passes After Failure
+  auto_generated_guid: 00000000-0000-0000-0000-000000000003
+  description: Exits successfully
+  supported_platforms:
+  - linux
+  - macos
+  executor:
+    command: exit 0
+    name: sh
+- name: Wrong OS
+  auto_generated_guid: 00000000-0000-0000-0000-000000000004
+  description: Only runs on a platform that does not exist
+  supported_platforms:
+  - plan9
+  executor:
+    command: exit 0
+    name: sh
+";
+
+    #[cfg(unix)]
+    #[test]
+    fn run_all_keeps_going() {
+        let art_path = corpus("T0000.001", TECHNIQUE);
+
+        let options = RunOptions {
+            max_jobs: 2,
+            ..Default::default()
+        };
+        let results = run_all(&[art_path.path().to_owned()], &options).unwrap();
+
+        assert_eq!(results.len(), 4);
+        assert!(matches!(results[0].outcome, TestOutcome::Passed));
+        assert!(matches!(results[1].outcome, TestOutcome::Failed(_)));
+        assert!(matches!(results[2].outcome, TestOutcome::Passed));
+        assert!(matches!(
+            results[3].outcome,
+            TestOutcome::Skipped(ArrError::OsNotSupported)
+        ));
+
+        // a failed test keeps the output of what ran
+        let attack = |result: &TestResult| {
+            result
+                .report
+                .as_ref()
+                .and_then(|r| r.phase(Phase::Attack).next())
+                .and_then(|p| p.exit_code)
+        };
+        assert_eq!(attack(&results[0]), Some(0));
+        assert_eq!(attack(&results[1]), Some(1));
+        assert!(results[3].report.is_none());
+
+        let json = serde_json::to_value(&results[1]).unwrap();
+        assert_eq!(json["status"], "failed");
+        assert_eq!(json["report"]["phases"][0]["exit_code"], 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_all_sets_vars() {
+        let art_path = corpus(
+            "T0000.025",
+            "attack_technique: T0000.025
+display_name: Run All Vars
+atomic_tests:
+- name: Exit Code
+  auto_generated_guid: 00000000-0000-0000-0000-000000000025
+  description: Exits with the code given
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    code:
+      description: The exit code
+      type: integer
+      default: 1
+  executor:
+    command: 'exit #{code}'
+    name: sh
+- name: Script
+  auto_generated_guid: 00000000-0000-0000-0000-000000000026
+  description: Runs the script given
+  supported_platforms:
+  - linux
+  - macos
+  input_arguments:
+    script:
+      description: The script to run
+      type: string
+      default: exit 1
+  executor:
+    command: '#{script}'
+    name: sh
+",
+        );
+        let run = |vars: &[(&str, &str)], raw_vars| {
+            let options = RunOptions {
+                vars: vars
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect(),
+                raw_vars,
+                ..Default::default()
+            };
+            run_all(&[art_path.path().to_owned()], &options)
+        };
+        let passed = |results: Result<Vec<TestResult>, ArrError>| {
+            results
+                .unwrap()
+                .iter()
+                .map(|r| matches!(r.outcome, TestOutcome::Passed))
+                .collect::<Vec<_>>()
+        };
+
+        let vars = [("code", "0"), ("script", "exit 0")];
+        assert_eq!(passed(run(&vars, false)), [true, false]);
+        assert_eq!(passed(run(&vars, true)), [true, true]);
+
+        assert_eq!(
+            run(&[("scirpt", "x")], false).err(),
+            Some(ArrError::UnknownArgument {
+                name: "scirpt".to_string(),
+                closest: Some("script".to_string()),
+                available: vec!["code".to_string(), "script".to_string()],
+            })
+        );
+    }
+}
@@ -0,0 +1,417 @@
+use std::{collections::HashMap, env, fmt, fs, path::Path};
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+
+use crate::error::{ArrError, SourceError};
+
+/// Environment variables starting with this set the variable named by the rest.
+pub const ENV_PREFIX: &str = "ARR_VAR_";
+
+/// Where a variable's value came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ValueSource {
+    Default,
+    /// A default that uses a value the user gave
+    Derived,
+    VarsFile,
+    Environment,
+    SetVar,
+}
+
+impl fmt::Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValueSource::Default => write!(f, "default"),
+            ValueSource::Derived => write!(f, "derived"),
+            ValueSource::VarsFile => write!(f, "vars file"),
+            ValueSource::Environment => write!(f, "environment"),
+            ValueSource::SetVar => write!(f, "--set-var"),
+        }
+    }
+}
+
+/// Values for variables from outside `--set-var`, used by whichever tests take them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VarSources {
+    pub file: VarsFile,
+    pub env: HashMap<String, String>,
+}
+
+impl VarSources {
+    /// Read the `ARR_VAR_<name>` environment variables, and the vars file if there is one.
+    pub fn load(vars_file: Option<&Path>, profile: Option<&str>) -> Result<Self, ArrError> {
+        let env = env::vars()
+            .filter_map(|(name, value)| {
+                name.strip_prefix(ENV_PREFIX)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| (name.to_string(), value))
+            })
+            .collect();
+
+        let file = match (vars_file, profile) {
+            (Some(path), profile) => VarsFile::read(path, profile)?,
+            (None, Some(profile)) => {
+                return Err(ArrError::VarsFile(format!(
+                    "profile `{}` needs a vars file to be read from",
+                    profile
+                )))
+            }
+            (None, None) => VarsFile::default(),
+        };
+
+        Ok(Self { file, env })
+    }
+
+    /// Each source's values for a test, from lowest to highest precedence.
+    pub(crate) fn layers(
+        &self,
+        technique: &str,
+        guid: &str,
+    ) -> [(ValueSource, HashMap<String, String>); 2] {
+        [
+            (ValueSource::VarsFile, self.file.for_test(technique, guid)),
+            (ValueSource::Environment, self.env.clone()),
+        ]
+    }
+}
+
+/// A variable's value in a vars file, which may be written as a string, number or boolean.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Scalar(String);
+
+impl<'de> Deserialize<'de> for Scalar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Scalar;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a string, number or boolean")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Scalar, E> {
+                Ok(Scalar(v.to_string()))
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Scalar, E> {
+                Ok(Scalar(v.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Scalar, E> {
+                Ok(Scalar(v.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Scalar, E> {
+                Ok(Scalar(v.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Scalar, E> {
+                Ok(Scalar(v.to_string()))
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+type Scalars = HashMap<String, Scalar>;
+
+/// Values for every test, for a technique's tests, and for single tests.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Scope {
+    #[serde(default)]
+    vars: Scalars,
+    /// By technique ID
+    #[serde(default)]
+    techniques: HashMap<String, Scalars>,
+    /// By test GUID
+    #[serde(default)]
+    tests: HashMap<String, Scalars>,
+}
+
+impl Scope {
+    /// The values for a test, with the more specific scopes taking precedence.
+    fn for_test<'a>(
+        &'a self,
+        technique: &'a str,
+        guid: &'a str,
+    ) -> impl Iterator<Item = (&'a String, &'a Scalar)> + 'a {
+        self.vars
+            .iter()
+            .chain(scoped(&self.techniques, technique))
+            .chain(scoped(&self.tests, guid))
+    }
+}
+
+/// The values under a technique ID or GUID, which are matched whatever their case.
+fn scoped<'a>(
+    scopes: &'a HashMap<String, Scalars>,
+    key: &'a str,
+) -> impl Iterator<Item = (&'a String, &'a Scalar)> + 'a {
+    scopes
+        .iter()
+        .filter(move |(k, _)| k.trim().eq_ignore_ascii_case(key.trim()))
+        .flat_map(|(_, values)| values)
+}
+
+/// What a vars file holds.
+#[derive(Debug, Deserialize)]
+struct VarsFileContents {
+    #[serde(default)]
+    vars: Scalars,
+    #[serde(default)]
+    techniques: HashMap<String, Scalars>,
+    #[serde(default)]
+    tests: HashMap<String, Scalars>,
+    #[serde(default)]
+    profiles: HashMap<String, Scope>,
+    /// Variables for every test can be written at the top too
+    #[serde(flatten)]
+    shorthand: Scalars,
+}
+
+/// The values read from a vars file, and from the profile chosen in it.
+///
+/// The profile's values take precedence over the rest of the file. Within each, values
+/// for a test's GUID take precedence over those for its technique, which take precedence
+/// over those for every test.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VarsFile {
+    scopes: Vec<Scope>,
+}
+
+impl From<HashMap<String, String>> for VarsFile {
+    /// Values for every test.
+    fn from(vars: HashMap<String, String>) -> Self {
+        Self {
+            scopes: vec![Scope {
+                vars: vars.into_iter().map(|(k, v)| (k, Scalar(v))).collect(),
+                ..Default::default()
+            }],
+        }
+    }
+}
+
+impl VarsFile {
+    /// Read a YAML, TOML or JSON vars file, going by its extension, and choose one of its profiles.
+    pub fn read(path: &Path, profile: Option<&str>) -> Result<Self, ArrError> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| ArrError::CannotReadFile(SourceError::unreadable(path.to_owned(), &e)))?;
+
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        let contents: VarsFileContents = match extension.as_str() {
+            "toml" => {
+                toml::from_str(&text).map_err(|e| SourceError::from_toml(path.into(), &text, &e))
+            }
+            "json" => serde_json::from_str(&text)
+                .map_err(|e| SourceError::from_json(path.into(), &text, &e)),
+            // an empty YAML file is a null rather than an empty mapping
+            _ if text.trim().is_empty() => serde_yaml::from_str("{}")
+                .map_err(|e| SourceError::from_yaml(path.into(), &text, &e)),
+            _ => serde_yaml::from_str(&text)
+                .map_err(|e| SourceError::from_yaml(path.into(), &text, &e)),
+        }
+        .map_err(ArrError::CannotParseVarsFile)?;
+
+        let mut vars = contents.shorthand;
+        vars.extend(contents.vars);
+        let mut scopes = vec![Scope {
+            vars,
+            techniques: contents.techniques,
+            tests: contents.tests,
+        }];
+
+        if let Some(profile) = profile {
+            match contents.profiles.get(profile) {
+                Some(scope) => scopes.push(scope.clone()),
+                None => {
+                    let mut profiles: Vec<&String> = contents.profiles.keys().collect();
+                    profiles.sort();
+
+                    return Err(ArrError::VarsFile(match profiles.is_empty() {
+                        true => format!("{} has no profiles", path.display()),
+                        false => format!(
+                            "{} has no profile `{}`, its profiles are: {}",
+                            path.display(),
+                            profile,
+                            profiles
+                                .iter()
+                                .map(|p| p.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        ),
+                    }));
+                }
+            }
+        }
+
+        Ok(Self { scopes })
+    }
+
+    /// The values for one test, with the more specific ones taking precedence.
+    pub fn for_test(&self, technique: &str, guid: &str) -> HashMap<String, String> {
+        self.scopes
+            .iter()
+            .flat_map(|scope| scope.for_test(technique, guid))
+            .map(|(name, value)| (name.clone(), value.0.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const GUID: &str = "39cb0e67-dd0d-4b74-a74b-c072db7ae991";
+
+    fn write(dir: &Path, name: &str, text: &str) -> std::path::PathBuf {
+        let path = dir.join(name);
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    #[test]
+    fn vars_files_are_scoped() {
+        let tmp = tempfile::tempdir().unwrap();
+        let yaml = write(
+            tmp.path(),
+            "vars.yaml",
+            "count: 3
+vars:
+  output_dir: /tmp
+  query: a=b&c=d
+techniques:
+  t1574.006:
+    output_dir: /var/tmp
+    verbose: true
+tests:
+  39CB0E67-DD0D-4B74-A74B-C072DB7AE991:
+    output_dir: /opt
+profiles:
+  lab:
+    vars:
+      count: 1.5
+  prod-canary:
+    techniques:
+      T1574.006:
+        output_dir: /srv
+",
+        );
+        let toml = write(
+            tmp.path(),
+            "vars.toml",
+            r#"count = 3
+[vars]
+output_dir = "/tmp"
+query = "a=b&c=d"
+[techniques."T1574.006"]
+output_dir = "/var/tmp"
+verbose = true
+[tests."39cb0e67-dd0d-4b74-a74b-c072db7ae991"]
+output_dir = "/opt"
+[profiles.lab.vars]
+count = 1.5
+[profiles.prod-canary.techniques."T1574.006"]
+output_dir = "/srv"
+"#,
+        );
+        let json = write(
+            tmp.path(),
+            "vars.json",
+            r#"{
+  "count": 3,
+  "vars": {"output_dir": "/tmp", "query": "a=b&c=d"},
+  "techniques": {"T1574.006": {"output_dir": "/var/tmp", "verbose": true}},
+  "tests": {"39cb0e67-dd0d-4b74-a74b-c072db7ae991": {"output_dir": "/opt"}},
+  "profiles": {
+    "lab": {"vars": {"count": 1.5}},
+    "prod-canary": {"techniques": {"T1574.006": {"output_dir": "/srv"}}}
+  }
+}"#,
+        );
+
+        for path in [yaml, toml, json] {
+            let read = |profile| VarsFile::read(&path, profile).unwrap();
+            let value = |file: &VarsFile, technique, guid, name: &str| {
+                file.for_test(technique, guid).get(name).cloned()
+            };
+
+            let file = read(None);
+            assert_eq!(
+                value(&file, "T1003", "", "output_dir").as_deref(),
+                Some("/tmp")
+            );
+            assert_eq!(
+                value(&file, "T1003", "", "query").as_deref(),
+                Some("a=b&c=d")
+            );
+            assert_eq!(value(&file, "T1003", "", "count").as_deref(), Some("3"));
+            assert_eq!(
+                value(&file, "T1574.006", "", "output_dir").as_deref(),
+                Some("/var/tmp")
+            );
+            assert_eq!(
+                value(&file, "T1574.006", "", "verbose").as_deref(),
+                Some("true")
+            );
+            assert_eq!(
+                value(&file, "T1574.006", GUID, "output_dir").as_deref(),
+                Some("/opt")
+            );
+
+            let lab = read(Some("lab"));
+            assert_eq!(value(&lab, "T1003", "", "count").as_deref(), Some("1.5"));
+
+            // a profile takes precedence over the rest of the file, even its tests
+            let canary = read(Some("prod-canary"));
+            assert_eq!(
+                value(&canary, "T1574.006", GUID, "output_dir").as_deref(),
+                Some("/srv")
+            );
+
+            assert_eq!(
+                VarsFile::read(&path, Some("prod")),
+                Err(ArrError::VarsFile(format!(
+                    "{} has no profile `prod`, its profiles are: lab, prod-canary",
+                    path.display()
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn vars_file_errors_are_located() {
+        let tmp = tempfile::tempdir().unwrap();
+
+        let yaml = write(tmp.path(), "vars.yaml", "vars:\n  files:\n  - a\n");
+        let toml = write(tmp.path(), "vars.toml", "[vars]\nfiles = [\"a\"]\n");
+        let json = write(
+            tmp.path(),
+            "vars.json",
+            "{\n  \"vars\": {\"files\": [\"a\"]}\n}",
+        );
+
+        for (path, line) in [(yaml, 3), (toml, 2), (json, 2)] {
+            match VarsFile::read(&path, None) {
+                Err(ArrError::CannotParseVarsFile(e)) => {
+                    assert_eq!(e.line, Some(line), "{}", e);
+                    assert!(e.message.contains("a string, number or boolean"), "{}", e);
+                }
+                res => panic!("{} was read: {:?}", path.display(), res),
+            }
+        }
+
+        let empty = write(tmp.path(), "empty.yaml", "");
+        assert_eq!(
+            VarsFile::read(&empty, None),
+            Ok(VarsFile::from(HashMap::new()))
+        );
+    }
+}